@@ -48,14 +48,14 @@ type Height = usize;
 type SquareFeet = usize;
 
 fn read_list(list: &str) -> Vec<Present> {
-    list.lines().map(|dims| Present::from(dims)).collect()
+    list.lines().map(Present::from).collect()
 }
 
-fn total_wrapping_paper(presents: &Vec<Present>) -> SquareFeet {
+fn total_wrapping_paper(presents: &[Present]) -> SquareFeet {
     presents.iter().map(|p| p.surface_area()).sum()
 }
 
-fn total_ribbon(presents: &Vec<Present>) -> Length {
+fn total_ribbon(presents: &[Present]) -> Length {
     presents.iter().map(|p| p.required_ribbon()).sum()
 }
 
@@ -0,0 +1,136 @@
+/*!
+Search progress persisted between runs.
+
+Each line of a checkpoint file is tab separated:
+
+    secret  hash  difficulty  checked|found  nonce
+
+`checked N` means every nonce below `N` has been tried without success, and
+`found N` records the lowest valid nonce.
+*/
+
+use super::difficulty::Difficulty;
+use super::{Answer, Outcome, SecretKey};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Entry {
+    Checked(Answer),
+    Found(Answer),
+}
+
+impl From<Outcome> for Entry {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Found(answer) => Entry::Found(answer),
+            Outcome::NotFoundBelow(checked) | Outcome::Cancelled(checked) => {
+                Entry::Checked(checked)
+            }
+        }
+    }
+}
+
+type Key = (String, String, String);
+
+pub struct Checkpoints {
+    path: PathBuf,
+    entries: BTreeMap<Key, Entry>,
+}
+
+impl Checkpoints {
+    /// Reads the checkpoint file at `path`; a missing file starts empty.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = BTreeMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let malformed = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}:{}: malformed checkpoint {:?}",
+                        path.display(),
+                        i + 1,
+                        line
+                    ),
+                )
+            };
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (secret, hash, difficulty, kind, nonce) = match fields.as_slice() {
+                [secret, hash, difficulty, kind, nonce] => (secret, hash, difficulty, kind, nonce),
+                _ => return Err(malformed()),
+            };
+            let nonce = nonce.parse().map_err(|_| malformed())?;
+            let entry = match *kind {
+                "checked" => Entry::Checked(nonce),
+                "found" => Entry::Found(nonce),
+                _ => return Err(malformed()),
+            };
+            entries.insert(
+                (secret.to_string(), hash.to_string(), difficulty.to_string()),
+                entry,
+            );
+        }
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn get(&self, secret: SecretKey, hash: &str, difficulty: &Difficulty) -> Option<Entry> {
+        self.entries.get(&key(secret, hash, difficulty)).copied()
+    }
+
+    /// Records progress and rewrites the file. A `found` entry is final and
+    /// `checked` never moves backwards.
+    pub fn record(
+        &mut self,
+        secret: SecretKey,
+        hash: &str,
+        difficulty: &Difficulty,
+        entry: Entry,
+    ) -> io::Result<()> {
+        let current = self
+            .entries
+            .entry(key(secret, hash, difficulty))
+            .or_insert(entry);
+        *current = match (*current, entry) {
+            (Entry::Found(answer), _) => Entry::Found(answer),
+            (Entry::Checked(old), Entry::Checked(new)) => Entry::Checked(old.max(new)),
+            (Entry::Checked(_), found) => found,
+        };
+        self.save()
+    }
+
+    /// Writes to a temporary file first so a kill mid-write keeps the old
+    /// checkpoint intact.
+    fn save(&self) -> io::Result<()> {
+        let mut contents = String::new();
+        for ((secret, hash, difficulty), entry) in &self.entries {
+            let (kind, nonce) = match entry {
+                Entry::Checked(nonce) => ("checked", nonce),
+                Entry::Found(nonce) => ("found", nonce),
+            };
+            contents += &format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                secret, hash, difficulty, kind, nonce
+            );
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn key(secret: SecretKey, hash: &str, difficulty: &Difficulty) -> Key {
+    (secret.to_string(), hash.to_string(), difficulty.to_string())
+}
@@ -0,0 +1,117 @@
+/*!
+Proof-of-work predicates over a raw digest.
+
+A difficulty is written as `kind:value`, e.g. `zeros:5` (the puzzle's leading
+hex zeros), `bits:20`, `below:00000fff` or `pattern:00??ab`.
+*/
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    /// The digest starts with at least this many zero bits.
+    LeadingZeroBits(usize),
+    /// The hex digest starts with at least this many `0`s.
+    LeadingHexZeros(usize),
+    /// The digest, read as a big-endian number, is below the target padded
+    /// with zero bytes to the digest length.
+    BelowTarget(Vec<u8>),
+    /// The hex digest starts with this pattern, where `?` matches any digit.
+    HexPattern(String),
+}
+
+impl Difficulty {
+    pub fn is_met(&self, digest: &[u8]) -> bool {
+        match self {
+            Difficulty::LeadingZeroBits(n) => leading_zero_bits(digest) >= *n,
+            Difficulty::LeadingHexZeros(n) => has_n_leading_zeros(digest, *n),
+            Difficulty::BelowTarget(target) => {
+                target.len() <= digest.len() && &digest[..target.len()] < target.as_slice()
+            }
+            Difficulty::HexPattern(pattern) => {
+                pattern.len() <= 2 * digest.len()
+                    && pattern.bytes().enumerate().all(|(i, expected)| {
+                        expected == b'?' || expected == HEX_DIGITS[nibble(digest, i) as usize]
+                    })
+            }
+        }
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+fn nibble(digest: &[u8], i: usize) -> u8 {
+    if i.is_multiple_of(2) {
+        digest[i / 2] >> 4
+    } else {
+        digest[i / 2] & 0xf
+    }
+}
+
+fn leading_zero_bits(digest: &[u8]) -> usize {
+    match digest.iter().position(|byte| *byte != 0) {
+        Some(i) => 8 * i + digest[i].leading_zeros() as usize,
+        None => 8 * digest.len(),
+    }
+}
+
+pub fn has_n_leading_zeros(digest: &[u8], n: usize) -> bool {
+    if n > 2 * digest.len() {
+        return false;
+    }
+    let (bytes, half) = (n / 2, n % 2);
+    digest[..bytes].iter().all(|byte| *byte == 0) && (half == 0 || digest[bytes] >> 4 == 0)
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::LeadingZeroBits(n) => write!(f, "bits:{}", n),
+            Difficulty::LeadingHexZeros(n) => write!(f, "zeros:{}", n),
+            Difficulty::BelowTarget(target) => {
+                write!(f, "below:")?;
+                target.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+            Difficulty::HexPattern(pattern) => write!(f, "pattern:{}", pattern),
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colon = s
+            .find(':')
+            .ok_or_else(|| format!("expected kind:value, got {:?}", s))?;
+        let (kind, value) = (&s[..colon], &s[colon + 1..]);
+        let count = || {
+            value
+                .parse()
+                .map_err(|_| format!("bad count in difficulty {:?}", s))
+        };
+        let is_hex = |allow_wildcard: bool| {
+            !value.is_empty()
+                && value
+                    .bytes()
+                    .all(|c| HEX_DIGITS.contains(&c) || (allow_wildcard && c == b'?'))
+        };
+
+        match kind {
+            "bits" => Ok(Difficulty::LeadingZeroBits(count()?)),
+            "zeros" => Ok(Difficulty::LeadingHexZeros(count()?)),
+            "below" if is_hex(false) && value.len().is_multiple_of(2) => {
+                Ok(Difficulty::BelowTarget(
+                    (0..value.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap())
+                        .collect(),
+                ))
+            }
+            "pattern" if is_hex(true) => Ok(Difficulty::HexPattern(value.to_string())),
+            "below" | "pattern" => Err(format!("bad hex in difficulty {:?}", s)),
+            _ => Err(format!("unknown difficulty kind {:?}", kind)),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+/*!
+Hash functions the miner can search with.
+
+MD5 is always available; SHA-1 and SHA-256 are enabled with the `sha1` and
+`sha2` cargo features.
+*/
+
+use super::{Answer, SecretKey};
+
+/// An incremental hash function. The state after consuming the secret key is
+/// cloned for every candidate nonce.
+pub trait HashAlgorithm: Clone + Send + Sync {
+    type Digest: AsRef<[u8]>;
+
+    const NAME: &'static str;
+
+    fn new() -> Self;
+
+    fn update(&mut self, data: &[u8]);
+
+    fn finish(self) -> Self::Digest;
+}
+
+#[derive(Clone)]
+pub struct Md5(md5::Context);
+
+impl HashAlgorithm for Md5 {
+    type Digest = [u8; 16];
+
+    const NAME: &'static str = "md5";
+
+    fn new() -> Self {
+        Self(md5::Context::new())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.consume(data);
+    }
+
+    fn finish(self) -> Self::Digest {
+        self.0.compute().0
+    }
+}
+
+#[cfg(feature = "sha1")]
+#[derive(Clone)]
+pub struct Sha1(sha1::Sha1);
+
+#[cfg(feature = "sha1")]
+impl HashAlgorithm for Sha1 {
+    type Digest = [u8; 20];
+
+    const NAME: &'static str = "sha1";
+
+    fn new() -> Self {
+        Self(sha1::Digest::new())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        sha1::Digest::update(&mut self.0, data);
+    }
+
+    fn finish(self) -> Self::Digest {
+        sha1::Digest::finalize(self.0).into()
+    }
+}
+
+#[cfg(feature = "sha2")]
+#[derive(Clone)]
+pub struct Sha256(sha2::Sha256);
+
+#[cfg(feature = "sha2")]
+impl HashAlgorithm for Sha256 {
+    type Digest = [u8; 32];
+
+    const NAME: &'static str = "sha256";
+
+    fn new() -> Self {
+        Self(sha2::Digest::new())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(&mut self.0, data);
+    }
+
+    fn finish(self) -> Self::Digest {
+        sha2::Digest::finalize(self.0).into()
+    }
+}
+
+/// Hash state after consuming the secret key, so each candidate only hashes
+/// its own digits.
+#[derive(Clone)]
+pub struct Hasher<H> {
+    prefix: H,
+}
+
+impl<H: HashAlgorithm> Hasher<H> {
+    pub fn new(secret: SecretKey) -> Self {
+        let mut prefix = H::new();
+        prefix.update(secret.as_bytes());
+        Self { prefix }
+    }
+
+    pub fn hash(&self, answer: Answer) -> H::Digest {
+        let mut buf = [0; 20];
+        let mut state = self.prefix.clone();
+        state.update(write_decimal(answer, &mut buf));
+        state.finish()
+    }
+}
+
+/// Writes the decimal digits of `n` into the tail of `buf` without allocating.
+fn write_decimal(mut n: usize, buf: &mut [u8; 20]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return &buf[start..];
+        }
+    }
+}
@@ -15,46 +15,454 @@ For example:
 Now find one that starts with six zeroes.
 
 */
-use md5;
-use std::io;
+mod checkpoint;
+mod difficulty;
+mod hash;
+
+use checkpoint::{Checkpoints, Entry};
+#[cfg(test)]
+use difficulty::has_n_leading_zeros;
+use difficulty::Difficulty;
+use hash::{HashAlgorithm, Hasher, Md5};
+
+use util::{flag_values, invalid_data, load, parse_flag, positional};
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt;
+use std::io::{self, Read};
+use std::iter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 fn main() -> io::Result<()> {
-    let secret: SecretKey = "iwrupvqb";
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    println!("part1: {}", lowest_number_with_n_leading_zeros(secret, 5));
-    println!("part2: {}", lowest_number_with_n_leading_zeros(secret, 6));
+    let contents = match positional(&args, &VALUE_FLAGS).as_slice() {
+        [] => load("src/day4/input.txt")?,
+        ["-"] => {
+            let mut contents = String::new();
+            io::stdin().read_to_string(&mut contents)?;
+            contents
+        }
+        secrets => secrets.join("\n"),
+    };
+    let secrets = read_secrets(&contents);
+
+    if args.iter().any(|arg| arg == "--bench") {
+        if let Some(secret) = secrets.first() {
+            bench(secret);
+        }
+        return Ok(());
+    }
+
+    let mut difficulties = flag_values(&args, "--difficulty")
+        .map(str::parse)
+        .collect::<Result<Vec<Difficulty>, _>>()
+        .map_err(invalid_data)?;
+    if difficulties.is_empty() {
+        difficulties = vec![
+            Difficulty::LeadingHexZeros(5),
+            Difficulty::LeadingHexZeros(6),
+        ];
+    }
+    let hash = flag_values(&args, "--hash").last().unwrap_or(Md5::NAME);
+    let checkpoints = match flag_values(&args, "--checkpoint").last() {
+        Some(path) => Some(Mutex::new(Checkpoints::load(path)?)),
+        None => None,
+    };
+
+    let mut miner = Miner::new(available_threads());
+    if let Some(max) = flag_values(&args, "--max-nonce").last() {
+        miner.max_nonce = Some(parse_flag("--max-nonce", max)?);
+    }
+    let never = AtomicBool::new(false);
+    let rows = secrets
+        .iter()
+        .map(|secret| {
+            NonceRow::mine(
+                &miner,
+                hash,
+                secret,
+                &difficulties,
+                &never,
+                checkpoints.as_ref(),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid_data)?;
+
+    print!("{}", format_table(&difficulties, &rows));
 
     Ok(())
 }
 
+const VALUE_FLAGS: [&str; 4] = ["--difficulty", "--hash", "--checkpoint", "--max-nonce"];
+
+/// One secret key per line; blank lines are ignored.
+fn read_secrets(contents: &str) -> Vec<SecretKey<'_>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// The lowest nonce for each difficulty, in order, for a single secret.
+struct NonceRow<'a> {
+    secret: SecretKey<'a>,
+    nonces: Vec<Outcome>,
+    hashes: usize,
+}
+
+impl<'a> NonceRow<'a> {
+    /// Resumes each search from `checkpoints` when given, and records its
+    /// progress there as it goes.
+    fn mine(
+        miner: &Miner,
+        hash: &str,
+        secret: SecretKey<'a>,
+        difficulties: &[Difficulty],
+        cancel: &AtomicBool,
+        checkpoints: Option<&Mutex<Checkpoints>>,
+    ) -> Result<Self, String> {
+        let mut row = Self {
+            secret,
+            nonces: Vec::new(),
+            hashes: 0,
+        };
+        for difficulty in difficulties {
+            let entry = checkpoints.and_then(|c| c.lock().unwrap().get(secret, hash, difficulty));
+            let start = match entry {
+                Some(Entry::Found(answer)) => {
+                    row.nonces.push(Outcome::Found(answer));
+                    continue;
+                }
+                Some(Entry::Checked(checked)) => checked,
+                None => 0,
+            };
+
+            let record = |entry| match checkpoints {
+                Some(checkpoints) => checkpoints
+                    .lock()
+                    .unwrap()
+                    .record(secret, hash, difficulty, entry)
+                    .map_err(|e| format!("checkpoint: {}", e)),
+                None => Ok(()),
+            };
+            let checkpoint = |checked| {
+                if let Err(e) = record(Entry::Checked(checked)) {
+                    eprintln!("{}", e);
+                }
+            };
+
+            let mined = mine_with(miner, hash, secret, difficulty, start, cancel, &checkpoint)?;
+            record(mined.outcome.into())?;
+            row.nonces.push(mined.outcome);
+            row.hashes += mined.hashes;
+        }
+        Ok(row)
+    }
+}
+
+fn format_table(difficulties: &[Difficulty], rows: &[NonceRow]) -> String {
+    let header: Vec<String> = iter::once("secret".to_string())
+        .chain(difficulties.iter().map(Difficulty::to_string))
+        .chain(iter::once("hashes".to_string()))
+        .collect();
+    let body: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            iter::once(row.secret.to_string())
+                .chain(row.nonces.iter().map(Outcome::to_string))
+                .chain(iter::once(row.hashes.to_string()))
+                .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            iter::once(&header)
+                .chain(&body)
+                .map(|cells| cells[i].len())
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    let mut table = String::new();
+    for cells in iter::once(&header).chain(&body) {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, width))| match i {
+                0 => format!("{:<1$}", cell, width),
+                _ => format!("{:>1$}", cell, width),
+            })
+            .collect();
+        table += line.join("  ").trim_end();
+        table += "\n";
+    }
+    table
+}
+
+/// Runs the miner with the hash algorithm named on the command line.
+fn mine_with(
+    miner: &Miner,
+    hash: &str,
+    secret: SecretKey,
+    difficulty: &Difficulty,
+    start: Answer,
+    cancel: &AtomicBool,
+    checkpoint: &(dyn Fn(Answer) + Sync),
+) -> Result<Mined, String> {
+    match hash {
+        "md5" => Ok(miner.resume::<Md5>(secret, difficulty, start, cancel, checkpoint)),
+        #[cfg(feature = "sha1")]
+        "sha1" => Ok(miner.resume::<hash::Sha1>(secret, difficulty, start, cancel, checkpoint)),
+        #[cfg(feature = "sha2")]
+        "sha256" => Ok(miner.resume::<hash::Sha256>(secret, difficulty, start, cancel, checkpoint)),
+        _ => Err(format!(
+            "unsupported hash {:?}; sha1 and sha256 need the sha1 and sha2 features",
+            hash
+        )),
+    }
+}
+
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn bench(secret: SecretKey) {
+    let miner = Miner::new(available_threads());
+    let never = AtomicBool::new(false);
+
+    for n in 5..=6 {
+        let start = Instant::now();
+        let answer = lowest_number_with_n_leading_zeros(secret, n);
+        let serial = Mined {
+            outcome: Outcome::Found(answer),
+            hashes: answer + 1,
+            elapsed: start.elapsed(),
+        };
+        let parallel = miner.mine::<Md5>(secret, &Difficulty::LeadingHexZeros(n), &never);
+        let threads = format!("{} threads", miner.threads);
+
+        for (name, mined) in [("serial", &serial), (threads.as_str(), &parallel)] {
+            println!(
+                "{} zeros, {:>10}: {:?} in {:?} ({:.0} hashes/sec)",
+                n,
+                name,
+                mined.answer(),
+                mined.elapsed,
+                mined.hashes_per_sec()
+            );
+        }
+    }
+}
+
 fn lowest_number_with_n_leading_zeros(secret: SecretKey, n: usize) -> Answer {
+    lowest_nonce::<Md5>(secret, &Difficulty::LeadingHexZeros(n))
+}
+
+fn lowest_nonce<H: HashAlgorithm>(secret: SecretKey, difficulty: &Difficulty) -> Answer {
+    let hasher = Hasher::<H>::new(secret);
     let mut answer = 0;
 
-    while !has_n_leading_zeros(&make_hash(secret, answer), n) {
+    while !difficulty.is_met(hasher.hash(answer).as_ref()) {
         answer += 1;
     }
 
     answer
 }
 
-type SecretKey<'a> = &'a str;
-type Answer = usize;
+/// Searches the nonce space on several threads at once.
+///
+/// Workers claim fixed-size chunks of nonces in increasing order and give up
+/// only once the next unclaimed chunk starts above the best answer found so
+/// far. Every chunk below that answer is therefore checked in full, so the
+/// result is the lowest valid nonce, just like the serial search.
+struct Miner {
+    threads: usize,
+    chunk_size: usize,
+    /// Give up with [`Outcome::NotFoundBelow`] instead of searching forever.
+    max_nonce: Option<Answer>,
+    checkpoint_interval: Duration,
+}
 
-fn make_hash(secret: SecretKey, answer: Answer) -> String {
-    format!("{:x}", md5::compute(format!("{}{}", secret, answer)))
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Found(Answer),
+    /// Every nonce below the bound was checked without success.
+    NotFoundBelow(Answer),
+    /// Stopped early; every nonce below the bound was checked.
+    Cancelled(Answer),
 }
 
-fn has_n_leading_zeros(s: &str, n: usize) -> bool {
-    if s.len() < n {
-        false
-    } else {
-        s.chars()
-            .enumerate()
-            .filter(|(i, _)| *i < n)
-            .all(|(_, c)| c == '0')
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Found(answer) => write!(f, "{}", answer),
+            Outcome::NotFoundBelow(max) => write!(f, "none < {}", max),
+            Outcome::Cancelled(_) => write!(f, "-"),
+        }
     }
 }
 
+/// Result of a [`Miner::mine`] run.
+struct Mined {
+    outcome: Outcome,
+    hashes: usize,
+    elapsed: Duration,
+}
+
+impl Mined {
+    fn answer(&self) -> Option<Answer> {
+        match self.outcome {
+            Outcome::Found(answer) => Some(answer),
+            _ => None,
+        }
+    }
+
+    fn hashes_per_sec(&self) -> f64 {
+        self.hashes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Chunks finished out of order, waiting for the ones below them.
+struct Progress {
+    checked: Answer,
+    done: BTreeSet<Answer>,
+    last_checkpoint: Instant,
+}
+
+impl Miner {
+    fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            chunk_size: 10_000,
+            max_nonce: None,
+            checkpoint_interval: Duration::from_secs(10),
+        }
+    }
+
+    fn mine<H: HashAlgorithm>(
+        &self,
+        secret: SecretKey,
+        difficulty: &Difficulty,
+        cancel: &AtomicBool,
+    ) -> Mined {
+        self.resume::<H>(secret, difficulty, 0, cancel, &|_| ())
+    }
+
+    /// Searches from `start`, assuming every nonce below it has been checked.
+    /// `checkpoint` is called at most once per `checkpoint_interval` with the
+    /// highest nonce below which everything has been checked.
+    fn resume<H: HashAlgorithm>(
+        &self,
+        secret: SecretKey,
+        difficulty: &Difficulty,
+        start: Answer,
+        cancel: &AtomicBool,
+        checkpoint: &(dyn Fn(Answer) + Sync),
+    ) -> Mined {
+        let started = Instant::now();
+        let max = self.max_nonce.unwrap_or(usize::MAX);
+        let next = AtomicUsize::new(start);
+        let best = AtomicUsize::new(usize::MAX);
+        let hashes = AtomicUsize::new(0);
+        let progress = Mutex::new(Progress {
+            checked: start,
+            done: BTreeSet::new(),
+            last_checkpoint: started,
+        });
+        // the highest checkpoint given to `checkpoint`, so reports stay in order
+        let reported = Mutex::new(start);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    let hasher = Hasher::<H>::new(secret);
+                    let mut tried = 0;
+                    while !cancel.load(Ordering::Relaxed) {
+                        let chunk = next.fetch_add(self.chunk_size, Ordering::Relaxed);
+                        if chunk >= best.load(Ordering::Relaxed) || chunk >= max {
+                            break;
+                        }
+                        let end = chunk.saturating_add(self.chunk_size).min(max);
+                        let mut complete = true;
+                        for answer in chunk..end {
+                            if answer >= best.load(Ordering::Relaxed) {
+                                complete = false;
+                                break;
+                            }
+                            tried += 1;
+                            if difficulty.is_met(hasher.hash(answer).as_ref()) {
+                                best.fetch_min(answer, Ordering::Relaxed);
+                                complete = false;
+                                break;
+                            }
+                        }
+                        if complete {
+                            let due = {
+                                let progress = &mut *progress.lock().unwrap();
+                                let before = progress.checked;
+                                progress.done.insert(chunk);
+                                while progress.done.remove(&progress.checked) {
+                                    progress.checked =
+                                        progress.checked.saturating_add(self.chunk_size).min(max);
+                                }
+                                let due =
+                                    progress.last_checkpoint.elapsed() >= self.checkpoint_interval;
+                                if progress.checked > before && due {
+                                    progress.last_checkpoint = Instant::now();
+                                    Some(progress.checked)
+                                } else {
+                                    None
+                                }
+                            };
+                            // written outside the progress lock, so other
+                            // workers finishing chunks never wait on the disk
+                            if let Some(checked) = due {
+                                let mut reported = reported.lock().unwrap();
+                                if checked > *reported {
+                                    checkpoint(checked);
+                                    *reported = checked;
+                                }
+                            }
+                        }
+                    }
+                    hashes.fetch_add(tried, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let checked = progress.into_inner().unwrap().checked;
+        let outcome = match best.into_inner() {
+            usize::MAX if cancel.load(Ordering::Relaxed) => Outcome::Cancelled(checked),
+            usize::MAX => Outcome::NotFoundBelow(max),
+            answer => Outcome::Found(answer),
+        };
+
+        Mined {
+            outcome,
+            hashes: hashes.into_inner(),
+            elapsed: started.elapsed(),
+        }
+    }
+}
+
+type SecretKey<'a> = &'a str;
+type Answer = usize;
+
+#[cfg(test)]
+fn make_hash(secret: SecretKey, answer: Answer) -> [u8; 16] {
+    Hasher::<Md5>::new(secret).hash(answer)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,4 +486,230 @@ mod tests {
         let secret: SecretKey = "pqrstuv";
         assert_eq!(lowest_number_with_n_leading_zeros(secret, 5), 1048970);
     }
+
+    #[test]
+    fn test_miner_finds_lowest_answer() {
+        let never = AtomicBool::new(false);
+
+        // small chunks so several workers race on chunks around the answer
+        let miner = Miner {
+            chunk_size: 997,
+            ..Miner::new(4)
+        };
+        let five = Difficulty::LeadingHexZeros(5);
+        assert_eq!(
+            miner.mine::<Md5>("abcdef", &five, &never).answer(),
+            Some(609043)
+        );
+        assert_eq!(
+            miner.mine::<Md5>("pqrstuv", &five, &never).answer(),
+            Some(1048970)
+        );
+
+        for n in 1..=3 {
+            assert_eq!(
+                miner
+                    .mine::<Md5>("abcdef", &Difficulty::LeadingHexZeros(n), &never)
+                    .answer(),
+                Some(lowest_number_with_n_leading_zeros("abcdef", n))
+            );
+        }
+    }
+
+    /// The digest of `data` in lowercase hex, and of `data` followed by `1`
+    /// through the hasher's saved secret key state.
+    fn hex_digests<H: HashAlgorithm>(data: &str) -> (String, String) {
+        let hex = |digest: H::Digest| {
+            let bytes = digest.as_ref().iter();
+            bytes.map(|byte| format!("{:02x}", byte)).collect()
+        };
+        let mut state = H::new();
+        state.update(data.as_bytes());
+        (hex(state.finish()), hex(Hasher::<H>::new(data).hash(1)))
+    }
+
+    #[test]
+    fn test_md5_known_answer() {
+        assert_eq!(
+            hex_digests::<Md5>("abc"),
+            (
+                "900150983cd24fb0d6963f7d28e17f72".to_string(),
+                "23734cd52ad4a4fb877d8a1e26e5df5f".to_string()
+            )
+        );
+    }
+
+    #[cfg(feature = "sha1")]
+    #[test]
+    fn test_sha1_known_answer() {
+        assert_eq!(
+            hex_digests::<hash::Sha1>("abc"),
+            (
+                "a9993e364706816aba3e25717850c26c9cd0d89d".to_string(),
+                "9ee036287b4cfbcfa3b5bbfcf92d46eb5e75df96".to_string()
+            )
+        );
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_sha256_known_answer() {
+        assert_eq!(
+            hex_digests::<hash::Sha256>("abc"),
+            (
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+                "dbfcfd0d87220f629339bd3adcf452d083fde3246625fb3a93e314f833e20d37".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_miner_cancelled() {
+        let cancel = AtomicBool::new(true);
+        let mined = Miner::new(2).mine::<Md5>("abcdef", &Difficulty::LeadingHexZeros(5), &cancel);
+        assert_eq!(mined.outcome, Outcome::Cancelled(0));
+        assert_eq!(mined.hashes, 0);
+    }
+
+    #[test]
+    fn test_difficulty_is_met() {
+        let digest = [0x00, 0x0f, 0xab, 0xff];
+        assert!(Difficulty::LeadingHexZeros(3).is_met(&digest));
+        assert!(!Difficulty::LeadingHexZeros(4).is_met(&digest));
+        assert!(Difficulty::LeadingZeroBits(12).is_met(&digest));
+        assert!(!Difficulty::LeadingZeroBits(13).is_met(&digest));
+        assert!(Difficulty::BelowTarget(vec![0x00, 0x10]).is_met(&digest));
+        assert!(!Difficulty::BelowTarget(vec![0x00, 0x0f]).is_met(&digest));
+        assert!(Difficulty::HexPattern("00?fa".to_string()).is_met(&digest));
+        assert!(!Difficulty::HexPattern("00?fb".to_string()).is_met(&digest));
+
+        // hex zeros and zero bits agree on the puzzle examples
+        let digest = make_hash("abcdef", 609043);
+        assert!(Difficulty::LeadingZeroBits(20).is_met(&digest));
+        assert!(Difficulty::HexPattern("000001dbbfa".to_string()).is_met(&digest));
+    }
+
+    #[test]
+    fn test_difficulty_from_str() {
+        for spec in &["bits:20", "zeros:5", "below:00000fff", "pattern:00??ab"] {
+            assert_eq!(spec.parse::<Difficulty>().unwrap().to_string(), *spec);
+        }
+        assert!("zeros".parse::<Difficulty>().is_err());
+        assert!("below:0g".parse::<Difficulty>().is_err());
+        assert!("below:000".parse::<Difficulty>().is_err());
+        assert!("cubes:3".parse::<Difficulty>().is_err());
+    }
+
+    #[test]
+    fn test_read_secrets() {
+        assert_eq!(
+            read_secrets("abcdef\n\n  pqrstuv \n"),
+            vec!["abcdef", "pqrstuv"]
+        );
+        assert!(read_secrets("").is_empty());
+    }
+
+    #[test]
+    fn test_format_table() {
+        let difficulties = [
+            Difficulty::LeadingHexZeros(5),
+            Difficulty::LeadingHexZeros(6),
+        ];
+        let rows = [
+            NonceRow {
+                secret: "abcdef",
+                nonces: vec![Outcome::Found(609043), Outcome::Found(6742839)],
+                hashes: 7351884,
+            },
+            NonceRow {
+                secret: "xyz",
+                nonces: vec![Outcome::Found(1), Outcome::NotFoundBelow(100)],
+                hashes: 2,
+            },
+        ];
+        assert_eq!(
+            format_table(&difficulties, &rows),
+            "\
+secret  zeros:5     zeros:6   hashes
+abcdef   609043     6742839  7351884
+xyz           1  none < 100        2
+"
+        );
+    }
+
+    #[test]
+    fn test_miner_max_nonce() {
+        let never = AtomicBool::new(false);
+        let five = Difficulty::LeadingHexZeros(5);
+        let mut miner = Miner {
+            chunk_size: 997,
+            max_nonce: Some(609043),
+            ..Miner::new(3)
+        };
+        let mined = miner.mine::<Md5>("abcdef", &five, &never);
+        assert_eq!(mined.outcome, Outcome::NotFoundBelow(609043));
+        assert_eq!(mined.hashes, 609043);
+
+        miner.max_nonce = Some(609044);
+        let mined = miner.mine::<Md5>("abcdef", &five, &never);
+        assert_eq!(mined.outcome, Outcome::Found(609043));
+    }
+
+    #[test]
+    fn test_miner_resume_and_checkpoint() {
+        let never = AtomicBool::new(false);
+        let five = Difficulty::LeadingHexZeros(5);
+        let miner = Miner {
+            chunk_size: 1000,
+            checkpoint_interval: Duration::from_secs(0),
+            ..Miner::new(2)
+        };
+        let highest = AtomicUsize::new(0);
+        let checkpoint = |checked| {
+            // chunks are reported in order, each one fully checked
+            assert_eq!(checked % 1000, 0);
+            assert!(checked > highest.swap(checked, Ordering::Relaxed));
+            assert!(checked <= 609043);
+        };
+        let mined = miner.resume::<Md5>("abcdef", &five, 600_000, &never, &checkpoint);
+        assert_eq!(mined.outcome, Outcome::Found(609043));
+        // the other worker may already be hashing chunks past the answer
+        assert!(mined.hashes >= 9044);
+        assert!(highest.into_inner() >= 608_000);
+    }
+
+    #[test]
+    fn test_checkpoints_round_trip() {
+        let path = env::temp_dir().join(format!("day4-checkpoint-{}.txt", std::process::id()));
+        let five = Difficulty::LeadingHexZeros(5);
+        let six = Difficulty::LeadingHexZeros(6);
+
+        let mut checkpoints = Checkpoints::load(&path).unwrap();
+        assert_eq!(checkpoints.get("abcdef", "md5", &five), None);
+        checkpoints
+            .record("abcdef", "md5", &five, Entry::Checked(500))
+            .unwrap();
+        checkpoints
+            .record("abcdef", "md5", &five, Entry::Checked(300))
+            .unwrap();
+        checkpoints
+            .record("abcdef", "md5", &six, Entry::Found(42))
+            .unwrap();
+        checkpoints
+            .record("abcdef", "md5", &six, Entry::Checked(900))
+            .unwrap();
+
+        let checkpoints = Checkpoints::load(&path).unwrap();
+        assert_eq!(
+            checkpoints.get("abcdef", "md5", &five),
+            Some(Entry::Checked(500))
+        );
+        assert_eq!(
+            checkpoints.get("abcdef", "md5", &six),
+            Some(Entry::Found(42))
+        );
+        assert_eq!(checkpoints.get("abcdef", "sha1", &six), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
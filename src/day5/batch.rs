@@ -0,0 +1,159 @@
+/*!
+Classifying word lists too large to hold in memory.
+
+Lines are streamed from the input in chunks, classified against every rule set
+at once on a pool of worker threads, and written back out in input order.
+*/
+
+use super::rules::Rule;
+use super::SantaString;
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct Batch<'a> {
+    pub rule_sets: Vec<&'a dyn Rule>,
+    pub threads: usize,
+    pub chunk_lines: usize,
+}
+
+/// Where to write the nice and naughty lines for one rule set.
+pub struct Partition<W> {
+    pub nice: W,
+    pub naughty: W,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub lines: usize,
+    /// Nice lines for each rule set, in order.
+    pub nice: Vec<usize>,
+}
+
+/// A chunk of lines and, per line, one bit per rule set that it satisfies.
+type Classified = (usize, Vec<String>, Vec<u64>);
+
+impl<'a> Batch<'a> {
+    pub fn new(rule_sets: Vec<&'a dyn Rule>, threads: usize) -> Self {
+        assert!(rule_sets.len() <= 64, "at most 64 rule sets per batch");
+        Self {
+            rule_sets,
+            threads: threads.max(1),
+            chunk_lines: 4096,
+        }
+    }
+
+    /// Classifies every line of `input`. When `partitions` is not empty it
+    /// must hold one partition per rule set.
+    pub fn classify<R, W>(&self, input: R, partitions: &mut [Partition<W>]) -> io::Result<Counts>
+    where
+        R: BufRead + Send,
+        W: Write,
+    {
+        assert!(partitions.is_empty() || partitions.len() == self.rule_sets.len());
+
+        let mut counts = Counts {
+            lines: 0,
+            nice: vec![0; self.rule_sets.len()],
+        };
+
+        thread::scope(|scope| {
+            // bounded so a fast reader cannot run far ahead of the workers
+            let (chunk_tx, chunk_rx) = mpsc::sync_channel::<(usize, Vec<String>)>(2 * self.threads);
+            let (done_tx, done_rx) = mpsc::sync_channel::<Classified>(2 * self.threads);
+            // workers own the receiver, so if tallying fails and the results
+            // stop being read, they exit and the reader's sends fail too
+            let chunk_rx = Arc::new(Mutex::new(chunk_rx));
+
+            let reader = scope.spawn(move || -> io::Result<()> {
+                let mut lines = input.lines();
+                for id in 0.. {
+                    let chunk = lines
+                        .by_ref()
+                        .take(self.chunk_lines)
+                        .collect::<io::Result<Vec<_>>>()?;
+                    if chunk.is_empty() || chunk_tx.send((id, chunk)).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            });
+
+            for _ in 0..self.threads {
+                let done_tx = done_tx.clone();
+                let chunk_rx = Arc::clone(&chunk_rx);
+                scope.spawn(move || loop {
+                    let received = chunk_rx.lock().unwrap().recv();
+                    let (id, chunk) = match received {
+                        Ok(received) => received,
+                        Err(_) => break,
+                    };
+                    let masks = chunk.iter().map(|line| self.mask(line)).collect();
+                    if done_tx.send((id, chunk, masks)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop((done_tx, chunk_rx));
+
+            // chunks finish out of order; hold them until their turn
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (id, chunk, masks) in done_rx {
+                pending.insert(id, (chunk, masks));
+                while let Some((chunk, masks)) = pending.remove(&next) {
+                    self.tally(&chunk, &masks, &mut counts, partitions)?;
+                    next += 1;
+                }
+            }
+
+            reader.join().unwrap()
+        })?;
+
+        for partition in partitions {
+            partition.nice.flush()?;
+            partition.naughty.flush()?;
+        }
+
+        Ok(counts)
+    }
+
+    fn mask(&self, line: &str) -> u64 {
+        let s = SantaString::from(line);
+        self.rule_sets
+            .iter()
+            .enumerate()
+            .filter(|(_, rules)| s.satisfies(**rules))
+            .fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+
+    fn tally<W: Write>(
+        &self,
+        chunk: &[String],
+        masks: &[u64],
+        counts: &mut Counts,
+        partitions: &mut [Partition<W>],
+    ) -> io::Result<()> {
+        counts.lines += chunk.len();
+        for (line, mask) in chunk.iter().zip(masks) {
+            for (i, nice) in counts.nice.iter_mut().enumerate() {
+                let is_nice = mask & 1 << i != 0;
+                if is_nice {
+                    *nice += 1;
+                }
+                if let Some(partition) = partitions.get_mut(i) {
+                    let out = if is_nice {
+                        &mut partition.nice
+                    } else {
+                        &mut partition.naughty
+                    };
+                    writeln!(out, "{}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
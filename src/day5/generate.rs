@@ -0,0 +1,531 @@
+/*!
+Counting and generating strings that satisfy a rule set.
+
+Every rule can be run as a small state machine over characters. Combining the
+machines of a rule set lets us count the nice strings of a given length with
+dynamic programming over their joint states, and sample nice strings uniformly
+by walking those counts.
+
+Most rules only need a few characters of history. `repeated_pair` has to
+remember every pair seen so far, which would make the number of states grow
+exponentially with length. To keep it in check, each time we read a letter
+that no rule singles out, we also decide whether it will be read again, and
+only count strings where that decision comes true. A letter that will not
+come back cannot complete a pair, so the machines can forget it. The letters
+still owed are at most the characters left, and that bounds what a state holds
+however long the strings are. Once they fill what is left, each comes exactly
+once more and none can repeat a pair with itself. Before memoising, owed
+letters are relabelled by when they were last read, which does not depend on
+which letters they are.
+*/
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use util::Lcg;
+
+pub type State = Vec<u32>;
+
+/// What `relabel` gives for a letter that will not be read again. It is not
+/// a `char`, so it never equals one.
+pub const GONE: u32 = char::MAX as u32 + 1;
+
+/// `relabel` gives letters from here up when every letter still owed will be
+/// read exactly once more, and nothing else will be.
+pub const ONCE: u32 = GONE + 1;
+
+pub trait Automaton: Send + Sync {
+    fn start(&self) -> State;
+
+    fn step(&self, state: &State, c: char) -> State;
+
+    fn accepts(&self, state: &State) -> bool;
+
+    /// Letters this automaton treats differently from the rest.
+    fn distinguished(&self) -> Vec<char> {
+        Vec::new()
+    }
+
+    /// Appends `state` to `out` with every letter in it passed through `map`.
+    /// Anything that only matters if a letter mapped to [`GONE`] comes back,
+    /// or one mapped to [`ONCE`] or above comes back twice, can be dropped.
+    fn relabel(&self, state: &State, _map: &mut dyn FnMut(u32) -> u32, out: &mut Vec<u32>) {
+        out.extend(state);
+    }
+}
+
+/// A rule as state machines joined by the same logic as the rule itself.
+pub enum Machine {
+    Atom(Box<dyn Automaton>),
+    All(Vec<Machine>),
+    Any(Vec<Machine>),
+    Not(Box<Machine>),
+}
+
+/// Which atoms must accept, referring to atoms by index.
+enum Formula {
+    Atom(usize),
+    All(Vec<Formula>),
+    Any(Vec<Formula>),
+    Not(Box<Formula>),
+}
+
+impl Formula {
+    fn build(machine: Machine, atoms: &mut Vec<Box<dyn Automaton>>) -> Self {
+        match machine {
+            Machine::Atom(atom) => {
+                atoms.push(atom);
+                Formula::Atom(atoms.len() - 1)
+            }
+            Machine::All(parts) => Formula::All(
+                parts
+                    .into_iter()
+                    .map(|part| Self::build(part, atoms))
+                    .collect(),
+            ),
+            Machine::Any(parts) => Formula::Any(
+                parts
+                    .into_iter()
+                    .map(|part| Self::build(part, atoms))
+                    .collect(),
+            ),
+            Machine::Not(inner) => Formula::Not(Box::new(Self::build(*inner, atoms))),
+        }
+    }
+
+    fn eval(&self, accepts: &dyn Fn(usize) -> bool) -> bool {
+        match self {
+            Formula::Atom(i) => accepts(*i),
+            Formula::All(parts) => parts.iter().all(|part| part.eval(accepts)),
+            Formula::Any(parts) => parts.iter().any(|part| part.eval(accepts)),
+            Formula::Not(inner) => !inner.eval(accepts),
+        }
+    }
+}
+
+/// A string partly read: the state of each atom, and which of the letters
+/// read so far will be read again.
+#[derive(Clone)]
+struct Walk {
+    states: Vec<State>,
+    /// Interchangeable letters that will be read again, least recent first.
+    owed: Vec<u32>,
+    /// Interchangeable letters that will not be read again.
+    gone: Vec<u32>,
+}
+
+pub struct Generator {
+    atoms: Vec<Box<dyn Automaton>>,
+    formula: Formula,
+    alphabet: Vec<char>,
+    /// Letters of the alphabet that every atom treats alike.
+    interchangeable: Vec<u32>,
+    /// Letters of the alphabet that some atom singles out.
+    distinguished: Vec<char>,
+    /// Accepted completions of each walk by how many unseen letters they
+    /// read, keyed as in `key`.
+    memo: HashMap<Vec<u32>, Vec<u128>>,
+    /// Reused to build keys, which mostly only get looked up.
+    key: Vec<u32>,
+}
+
+impl Generator {
+    /// Generates over the letters a to z.
+    pub fn new(machine: Machine) -> Self {
+        Self::with_alphabet(machine, ('a'..='z').collect())
+    }
+
+    pub fn with_alphabet(machine: Machine, alphabet: Vec<char>) -> Self {
+        let mut atoms = Vec::new();
+        let formula = Formula::build(machine, &mut atoms);
+        let singled_out: Vec<char> = atoms.iter().flat_map(|atom| atom.distinguished()).collect();
+        let (distinguished, interchangeable): (Vec<char>, Vec<char>) =
+            alphabet.iter().partition(|c| singled_out.contains(c));
+        Self {
+            atoms,
+            formula,
+            alphabet,
+            interchangeable: interchangeable.into_iter().map(|c| c as u32).collect(),
+            distinguished,
+            memo: HashMap::new(),
+            key: Vec::new(),
+        }
+    }
+
+    /// How many strings of length `len` satisfy the rules.
+    pub fn count(&mut self, len: usize) -> u128 {
+        let start = self.start();
+        self.completions(&start, len)
+    }
+
+    /// A string of length `len` drawn uniformly from those satisfying the
+    /// rules, or `None` if there are none.
+    pub fn sample(&mut self, len: usize, rng: &mut Lcg) -> Option<String> {
+        let mut walk = self.start();
+        let total = self.completions(&walk, len);
+        if total == 0 {
+            return None;
+        }
+
+        // `below` gives 31 bits at a time, and counts can need more than 64
+        let wide = (0..5).fold(0, |wide, _| wide << 31 | rng.below(1 << 31) as u128);
+        let mut pick = wide % total;
+        let mut s = String::new();
+        for remaining in (0..len).rev() {
+            'letters: for c in self.alphabet.clone() {
+                for next in self.moves(&walk, c, remaining) {
+                    let count = self.completions(&next, remaining);
+                    if pick < count {
+                        s.push(c);
+                        walk = next;
+                        break 'letters;
+                    }
+                    pick -= count;
+                }
+            }
+        }
+        Some(s)
+    }
+
+    fn start(&self) -> Walk {
+        Walk {
+            states: self.atoms.iter().map(|atom| atom.start()).collect(),
+            owed: Vec::new(),
+            gone: Vec::new(),
+        }
+    }
+
+    /// The walks that reading `c` can lead to with `left` characters to go
+    /// after it. An interchangeable letter is either read again later or
+    /// not, so it can lead to one walk for each, as long as the letters owed
+    /// still fit in what is left.
+    fn moves(&self, walk: &Walk, c: char, left: usize) -> Vec<Walk> {
+        let letter = c as u32;
+        if walk.gone.contains(&letter) {
+            return Vec::new();
+        }
+        let mut owed: Vec<u32> = walk.owed.iter().copied().filter(|&o| o != letter).collect();
+        if owed.len() > left {
+            return Vec::new();
+        }
+        let states: Vec<State> = self
+            .atoms
+            .iter()
+            .zip(&walk.states)
+            .map(|(atom, state)| atom.step(state, c))
+            .collect();
+        if !self.interchangeable.contains(&letter) {
+            return vec![Walk {
+                states,
+                owed,
+                gone: walk.gone.clone(),
+            }];
+        }
+
+        let mut gone = walk.gone.clone();
+        gone.push(letter);
+        let mut moves = vec![Walk {
+            states: states.clone(),
+            owed: owed.clone(),
+            gone,
+        }];
+        if owed.len() < left {
+            owed.push(letter);
+            moves.push(Walk {
+                states,
+                owed,
+                gone: walk.gone.clone(),
+            });
+        }
+        moves
+    }
+
+    /// Writes the memo key for `walk` to `key`. Owed letters are relabelled
+    /// by when they were last read and gone letters become [`GONE`], so walks
+    /// that differ only in which interchangeable letters they used share a
+    /// key.
+    fn key(&self, walk: &Walk, unseen: usize, remaining: usize, key: &mut Vec<u32>) {
+        // the owed letters fill what is left
+        let once = walk.owed.len() == remaining;
+        let mut map = |c: u32| match walk.owed.iter().position(|&o| o == c) {
+            Some(i) if once => ONCE + i as u32,
+            Some(i) => self.interchangeable[i],
+            None if walk.gone.contains(&c) => GONE,
+            None => c,
+        };
+        key.clear();
+        key.extend(&[remaining as u32, walk.owed.len() as u32, unseen as u32]);
+        for (atom, state) in self.atoms.iter().zip(&walk.states) {
+            // where each state ends, so they cannot run into each other
+            let start = key.len();
+            key.push(0);
+            atom.relabel(state, &mut map, key);
+            key[start] = (key.len() - start) as u32;
+        }
+    }
+
+    /// Interchangeable letters `walk` has not read yet.
+    fn unseen<'a>(&'a self, walk: &'a Walk) -> impl Iterator<Item = u32> + 'a {
+        self.interchangeable
+            .iter()
+            .copied()
+            .filter(move |c| !walk.owed.contains(c) && !walk.gone.contains(c))
+    }
+
+    fn completions(&mut self, walk: &Walk, remaining: usize) -> u128 {
+        let unseen = self.unseen(walk).count() as u128;
+        let mut by_new = vec![0; remaining + 1];
+        self.add_completions(walk, remaining, &mut by_new);
+        by_new
+            .iter()
+            .enumerate()
+            .map(|(new, count)| {
+                let choices: u128 = (0..new as u128).map(|i| unseen.saturating_sub(i)).product();
+                count * choices
+            })
+            .sum()
+    }
+
+    /// Adds the accepted completions of `walk` to `by_new`, split by how many
+    /// unseen letters they read, with the unseen letters always taken in the
+    /// same order: entry `k` leaves out the `unseen * (unseen - 1) * ...`
+    /// ways of picking `k` of them. How many letters are left to pick then
+    /// only matters once it is fewer than the characters left, so walks that
+    /// used different numbers of letters can share a memo entry.
+    fn add_completions(&mut self, walk: &Walk, remaining: usize, by_new: &mut [u128]) {
+        if remaining == 0 {
+            let atoms = &self.atoms;
+            let states = &walk.states;
+            by_new[0] += self.formula.eval(&|i| atoms[i].accepts(&states[i])) as u128;
+            return;
+        }
+        let mut unseen = self.unseen(walk);
+        // the first unseen letter stands in for the rest
+        let first_unseen = unseen.next();
+        let unseen = (first_unseen.is_some() as usize + unseen.count()).min(remaining);
+        let mut key = std::mem::take(&mut self.key);
+        self.key(walk, unseen, remaining, &mut key);
+        if let Some(counts) = self.memo.get(&key) {
+            for (total, count) in by_new.iter_mut().zip(counts) {
+                *total += count;
+            }
+            self.key = key;
+            return;
+        }
+
+        let mut counts = vec![0; remaining + 1];
+        let letters = self
+            .distinguished
+            .iter()
+            .copied()
+            .chain(walk.owed.iter().map(|&o| std::char::from_u32(o).unwrap()));
+        for c in letters.collect::<Vec<_>>() {
+            for next in self.moves(walk, c, remaining - 1) {
+                self.add_completions(&next, remaining - 1, &mut counts);
+            }
+        }
+        if let Some(c) = first_unseen.and_then(std::char::from_u32) {
+            for next in self.moves(walk, c, remaining - 1) {
+                self.add_completions(&next, remaining - 1, &mut counts[1..]);
+            }
+        }
+        for (total, count) in by_new.iter_mut().zip(&counts) {
+            *total += count;
+        }
+        self.memo.insert(key, counts);
+    }
+}
+
+// State layouts start with a "decided" flag where a rule's outcome can be
+// settled early; decided states drop their history so they merge.
+
+/// `[count]`, capped at the number needed.
+pub struct VowelCount(pub usize);
+
+impl Automaton for VowelCount {
+    fn start(&self) -> State {
+        vec![0]
+    }
+
+    fn step(&self, state: &State, c: char) -> State {
+        let vowel = matches!(c, 'a' | 'e' | 'i' | 'o' | 'u') as u32;
+        vec![(state[0] + vowel).min(self.0 as u32)]
+    }
+
+    fn accepts(&self, state: &State) -> bool {
+        state[0] as usize >= self.0
+    }
+
+    fn distinguished(&self) -> Vec<char> {
+        vec!['a', 'e', 'i', 'o', 'u']
+    }
+}
+
+/// `[found, last between + 1 characters...]`
+pub struct Sandwich(pub usize);
+
+impl Automaton for Sandwich {
+    fn start(&self) -> State {
+        vec![0]
+    }
+
+    fn step(&self, state: &State, c: char) -> State {
+        let history = &state[1..];
+        if state[0] == 1 || (history.len() == self.0 + 1 && history[0] == c as u32) {
+            return vec![1];
+        }
+        let keep = history.len().min(self.0);
+        let mut next = vec![0];
+        next.extend(&history[history.len() - keep..]);
+        next.push(c as u32);
+        next
+    }
+
+    fn accepts(&self, state: &State) -> bool {
+        state[0] == 1
+    }
+
+    fn relabel(&self, state: &State, map: &mut dyn FnMut(u32) -> u32, out: &mut Vec<u32>) {
+        out.push(state[0]);
+        out.extend(state[1..].iter().map(|&c| map(c)));
+    }
+}
+
+/// `[found, last longest - 1 characters...]`
+pub struct Forbid(pub Vec<Vec<char>>);
+
+impl Automaton for Forbid {
+    fn start(&self) -> State {
+        vec![0]
+    }
+
+    fn step(&self, state: &State, c: char) -> State {
+        if state[0] == 1 {
+            return vec![1];
+        }
+        let mut window: Vec<u32> = state[1..].to_vec();
+        window.push(c as u32);
+        let found = self.0.iter().any(|combo| {
+            combo.len() <= window.len()
+                && window[window.len() - combo.len()..]
+                    .iter()
+                    .zip(combo)
+                    .all(|(a, b)| *a == *b as u32)
+        });
+        if found {
+            return vec![1];
+        }
+        let longest = self.0.iter().map(Vec::len).max().unwrap_or(1);
+        let keep = window.len().min(longest.saturating_sub(1));
+        let mut next = vec![0];
+        next.extend(&window[window.len() - keep..]);
+        next
+    }
+
+    fn accepts(&self, state: &State) -> bool {
+        state[0] == 0
+    }
+
+    fn distinguished(&self) -> Vec<char> {
+        self.0.iter().flatten().copied().collect()
+    }
+
+    fn relabel(&self, state: &State, map: &mut dyn FnMut(u32) -> u32, out: &mut Vec<u32>) {
+        out.push(state[0]);
+        out.extend(state[1..].iter().map(|&c| map(c)));
+    }
+}
+
+/// `[found, previous character + 1 or 0, pending count, pending pairs...,
+/// eligible pairs...]` where pending pairs are too recent to match without
+/// overlapping and eligible pairs are kept sorted.
+pub struct RepeatedPair {
+    pub overlap: bool,
+}
+
+/// Where `pair` is, or would go, among sorted pairs laid out flat.
+fn find_pair(pairs: &[u32], pair: (u32, u32)) -> Result<usize, usize> {
+    let (mut low, mut high) = (0, pairs.len() / 2);
+    while low < high {
+        let mid = (low + high) / 2;
+        match (pairs[2 * mid], pairs[2 * mid + 1]).cmp(&pair) {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return Ok(mid),
+        }
+    }
+    Err(low)
+}
+
+impl Automaton for RepeatedPair {
+    fn start(&self) -> State {
+        vec![0, 0, 0]
+    }
+
+    fn step(&self, state: &State, c: char) -> State {
+        if state[0] == 1 {
+            return vec![1];
+        }
+        let letter = c as u32;
+        let mut next = state.clone();
+        next[1] = letter + 1;
+        if let Some(prev) = state[1].checked_sub(1) {
+            let eligible_at = 3 + 2 * state[2] as usize;
+            if find_pair(&state[eligible_at..], (prev, letter)).is_ok() {
+                return vec![1];
+            }
+            next.insert(eligible_at, letter);
+            next.insert(eligible_at, prev);
+            next[2] += 1;
+            let wait = if self.overlap { 0 } else { 1 };
+            while next[2] > wait {
+                let ready = (next.remove(3), next.remove(3));
+                next[2] -= 1;
+                let eligible_at = 3 + 2 * next[2] as usize;
+                if let Err(i) = find_pair(&next[eligible_at..], ready) {
+                    let at = eligible_at + 2 * i;
+                    next.insert(at, ready.1);
+                    next.insert(at, ready.0);
+                }
+            }
+        }
+        next
+    }
+
+    fn accepts(&self, state: &State) -> bool {
+        state[0] == 1
+    }
+
+    fn relabel(&self, state: &State, map: &mut dyn FnMut(u32) -> u32, out: &mut Vec<u32>) {
+        if state[0] == 1 {
+            out.push(1);
+            return;
+        }
+        // a pair can only come again if both letters do, except that the
+        // previous character is still there to start one, and a letter has
+        // to come twice in a row to repeat a pair with itself
+        let prev = state[1].checked_sub(1);
+        let pending_len = state[2] as usize;
+        out.extend(&[0, prev.map_or(0, |p| map(p) + 1), 0]);
+        let pending_at = out.len() - 1;
+        let mut eligible_at = out.len();
+        for (i, pair) in state[3..].chunks(2).enumerate() {
+            let (a, b) = (map(pair[0]), map(pair[1]));
+            let was_prev = Some(pair[0]) == prev;
+            if b == GONE || (a == GONE && !was_prev) || (a == b && a >= ONCE && !was_prev) {
+                continue;
+            }
+            if i < pending_len {
+                out.extend(&[a, b]);
+                out[pending_at] += 1;
+                eligible_at += 2;
+            } else {
+                // keep them sorted, as `step` does
+                let at =
+                    eligible_at + 2 * find_pair(&out[eligible_at..], (a, b)).unwrap_or_else(|i| i);
+                out.insert(at, b);
+                out.insert(at, a);
+            }
+        }
+    }
+}
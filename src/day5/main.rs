@@ -39,19 +39,182 @@ How many strings are nice under these new rules?
 
 */
 
-use util::load;
-
-use std::io;
+mod batch;
+mod generate;
+mod rules;
+
+use batch::{Batch, Partition};
+use generate::Generator;
+use rules::{parse_rules, All, Report, Rule};
+use util::{flag_value, load, parse_flag, positional, Lcg};
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let rule_files = positional(&args, &VALUE_FLAGS);
+
+    let loaded = rule_files
+        .iter()
+        .map(|path| {
+            parse_rules(&load(path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+        })
+        .collect::<io::Result<Vec<All>>>()?;
+    let rule_sets: Vec<(&str, &All)> = if loaded.is_empty() {
+        vec![("part1", nice_rules()), ("part2", nicer_rules())]
+    } else {
+        rule_files.iter().copied().zip(&loaded).collect()
+    };
+
+    if let Some(input) = flag_value(&args, "--batch") {
+        return batch(input, &rule_sets, &args);
+    }
+    if let Some(len) = flag_value(&args, "--count-nice") {
+        let len = parse_flag("--count-nice", len)?;
+        for (name, rules) in &rule_sets {
+            let nice = Generator::new(rules.machine()).count(len);
+            println!("{}: {} of 26^{} strings are nice", name, nice, len);
+        }
+        return Ok(());
+    }
+    if let Some(len) = flag_value(&args, "--generate") {
+        return generate(parse_flag("--generate", len)?, rule_sets[0].1, &args);
+    }
+
     let text = load("src/day5/input.txt")?;
 
-    println!("part1: {}", number_of_nice_strings(&text));
-    println!("part2: {}", number_of_nicer_strings(&text));
+    if args.iter().any(|arg| arg == "--explain") {
+        for (_, rules) in &rule_sets {
+            print!("{}", explain_naughty(&text, *rules));
+        }
+    } else if rule_files.is_empty() {
+        println!("part1: {}", number_of_nice_strings(&text));
+        println!("part2: {}", number_of_nicer_strings(&text));
+    } else {
+        for (path, rules) in &rule_sets {
+            println!("{}: {}", path, number_matching(&text, *rules));
+        }
+    }
+
+    Ok(())
+}
+
+const VALUE_FLAGS: [&str; 8] = [
+    "--batch",
+    "--out",
+    "--threads",
+    "--count-nice",
+    "--generate",
+    "--samples",
+    "--seed",
+    "--failing",
+];
+
+/// Classifies a whole word list (`-` for stdin) in one streaming pass,
+/// optionally writing `<name>.nice.txt` and `<name>.naughty.txt` per rule set
+/// into the `--out` directory.
+fn batch(input: &str, rule_sets: &[(&str, &All)], args: &[String]) -> io::Result<()> {
+    let threads = match flag_value(args, "--threads") {
+        Some(n) => parse_flag("--threads", n)?,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let mut partitions = Vec::new();
+    if let Some(dir) = flag_value(args, "--out") {
+        for (name, _) in rule_sets {
+            let stem = Path::new(name)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let create = |kind| -> io::Result<_> {
+                let path = Path::new(dir).join(format!("{}.{}.txt", stem, kind));
+                Ok(BufWriter::new(File::create(path)?))
+            };
+            partitions.push(Partition {
+                nice: create("nice")?,
+                naughty: create("naughty")?,
+            });
+        }
+    }
+
+    let batch = Batch::new(
+        rule_sets
+            .iter()
+            .map(|(_, rules)| *rules as &dyn Rule)
+            .collect(),
+        threads,
+    );
+    let counts = match input {
+        "-" => batch.classify(BufReader::new(io::stdin()), &mut partitions)?,
+        path => batch.classify(BufReader::new(File::open(path)?), &mut partitions)?,
+    };
+
+    println!("lines: {}", counts.lines);
+    for ((name, _), nice) in rule_sets.iter().zip(counts.nice) {
+        println!("{}: {} nice, {} naughty", name, nice, counts.lines - nice);
+    }
+
+    Ok(())
+}
+
+/// Prints `--samples` random strings of length `len` that are nice, or that
+/// fail only rule number `--failing` (counting from 1) of the rule set.
+fn generate(len: usize, rules: &All, args: &[String]) -> io::Result<()> {
+    let samples = match flag_value(args, "--samples") {
+        Some(n) => parse_flag("--samples", n)?,
+        None => 10,
+    };
+    let seed = match flag_value(args, "--seed") {
+        Some(seed) => parse_flag("--seed", seed)?,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |now| now.as_nanos() as u64),
+    };
+    let machine = match flag_value(args, "--failing") {
+        Some(n) => match parse_flag::<usize>("--failing", n)? {
+            n if (1..=rules.0.len()).contains(&n) => rules.machine_failing(n - 1),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such rule")),
+        },
+        None => rules.machine(),
+    };
+
+    let mut generator = Generator::new(machine);
+    let mut rng = Lcg(seed);
+    for _ in 0..samples {
+        match generator.sample(len, &mut rng) {
+            Some(s) => println!("{}", s),
+            None => break,
+        }
+    }
 
     Ok(())
 }
 
+/// Lists each naughty line with the rules it failed and why.
+fn explain_naughty(text: &str, rules: &dyn Rule) -> String {
+    let mut out = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let report = SantaString::from(line).explain(rules);
+        if report.passed {
+            continue;
+        }
+        out += &format!("line {}: {} is naughty\n", i + 1, line);
+        for failure in report.failures() {
+            for reason in failure.to_string().lines() {
+                out += &format!("  {}\n", reason);
+            }
+        }
+    }
+    out
+}
+
 fn number_of_nice_strings(text: &str) -> usize {
     text.lines()
         .filter(|s| SantaString::from(s).is_nice())
@@ -64,7 +227,21 @@ fn number_of_nicer_strings(text: &str) -> usize {
         .count()
 }
 
-const FORBIDDEN_COMBOS: [&'static str; 4] = ["ab", "cd", "pq", "xy"];
+fn number_matching(text: &str, rules: &dyn Rule) -> usize {
+    text.lines()
+        .filter(|s| SantaString::from(s).satisfies(rules))
+        .count()
+}
+
+fn nice_rules() -> &'static All {
+    static RULES: OnceLock<All> = OnceLock::new();
+    RULES.get_or_init(|| parse_rules(include_str!("part1.rules")).unwrap())
+}
+
+fn nicer_rules() -> &'static All {
+    static RULES: OnceLock<All> = OnceLock::new();
+    RULES.get_or_init(|| parse_rules(include_str!("part2.rules")).unwrap())
+}
 
 struct SantaString<'a>(&'a str);
 
@@ -74,82 +251,36 @@ impl<'a> SantaString<'a> {
     }
 
     fn is_nice(&self) -> bool {
-        self.contains_three_vowels() & self.has_repeated_letter() & self.has_no_forbidden_combos()
+        self.satisfies(nice_rules())
     }
 
     fn is_nicer(&self) -> bool {
-        self.has_repeated_pair_without_overlap() & self.contains_repeat_with_one_letter_between()
-    }
-
-    fn contains_three_vowels(&self) -> bool {
-        self.0
-            .chars()
-            .filter(|c| match c {
-                'a' | 'e' | 'i' | 'o' | 'u' => true,
-                _ => false,
-            })
-            .count()
-            >= 3
-    }
-
-    fn has_repeated_letter(&self) -> bool {
-        let mut prev = match self.0.chars().next() {
-            Some(p) => p,
-            _ => return false,
-        };
-        for c in self.0.chars().skip(1) {
-            if c == prev {
-                return true;
-            }
-            prev = c;
-        }
-        false
-    }
-
-    fn has_repeated_pair_without_overlap(&self) -> bool {
-        for i in 0..self.0.len() - 3 {
-            let this = &self.0[i..i + 2];
-            for j in i + 1..self.0.len() - 1 {
-                let that = &self.0[j..j + 2];
-                if this == that {
-                    return true;
-                }
-            }
-        }
-        false
+        self.satisfies(nicer_rules())
     }
 
-    fn contains_repeat_with_one_letter_between(&self) -> bool {
-        let mut chars = self.0.chars();
-        let mut prev2 = match chars.next() {
-            Some(p) => p,
-            _ => return false,
-        };
-        let mut prev = match chars.next() {
-            Some(p) => p,
-            _ => return false,
-        };
-
-        for c in self.0.chars().skip(2) {
-            if c == prev2 {
-                return true;
-            }
-            prev2 = prev;
-            prev = c;
-        }
-
-        false
+    fn satisfies(&self, rule: &dyn Rule) -> bool {
+        rule.matches(self.0)
     }
 
-    fn has_no_forbidden_combos(&self) -> bool {
-        FORBIDDEN_COMBOS.iter().all(|combo| !self.0.contains(combo))
+    fn explain(&self, rule: &dyn Rule) -> Report {
+        rule.explain(self.0)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::rules::{DoubleLetter, Forbid, MinVowels, RepeatedPair, Sandwich};
     use super::*;
 
+    fn forbidden_combos() -> Forbid {
+        Forbid(
+            ["ab", "cd", "pq", "xy"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        )
+    }
+
     #[test]
     fn test_is_nicer() {
         let s = SantaString::from("qjhvhtzxzqqjkmpb");
@@ -168,37 +299,40 @@ mod tests {
     #[test]
     fn test_contains_repeat_with_one_letter_between() {
         let s = SantaString::from("xyxy");
-        assert!(s.contains_repeat_with_one_letter_between());
+        assert!(s.satisfies(&Sandwich(1)));
 
         let s = SantaString::from("abcdefeghi");
-        assert!(s.contains_repeat_with_one_letter_between());
+        assert!(s.satisfies(&Sandwich(1)));
 
         let s = SantaString::from("aaa");
-        assert!(s.contains_repeat_with_one_letter_between());
+        assert!(s.satisfies(&Sandwich(1)));
 
         let s = SantaString::from("ieodomkazucvgmuy");
-        assert!(s.contains_repeat_with_one_letter_between());
+        assert!(s.satisfies(&Sandwich(1)));
     }
 
     #[test]
     fn test_has_repeated_pair_without_overlap() {
         let s = SantaString::from("xyxy");
-        assert!(s.has_repeated_pair_without_overlap());
+        assert!(s.satisfies(&RepeatedPair { overlap: false }));
 
         let s = SantaString::from("aabcdefgaa");
-        assert!(s.has_repeated_pair_without_overlap());
+        assert!(s.satisfies(&RepeatedPair { overlap: false }));
 
         let s = SantaString::from("aaa");
-        assert!(!s.has_repeated_pair_without_overlap());
+        assert!(!s.satisfies(&RepeatedPair { overlap: false }));
+
+        let s = SantaString::from("aaab");
+        assert!(!s.satisfies(&RepeatedPair { overlap: false }));
 
         let s = SantaString::from("aaaa");
-        assert!(s.has_repeated_pair_without_overlap());
+        assert!(s.satisfies(&RepeatedPair { overlap: false }));
 
         let s = SantaString::from("uurcxstgmygtbstg");
-        assert!(s.has_repeated_pair_without_overlap());
+        assert!(s.satisfies(&RepeatedPair { overlap: false }));
 
         let s = SantaString::from("ieodomkazucvgmuy");
-        assert!(!s.has_repeated_pair_without_overlap());
+        assert!(!s.satisfies(&RepeatedPair { overlap: false }));
     }
 
     #[test]
@@ -219,39 +353,240 @@ mod tests {
     #[test]
     fn test_contains_three_vowels() {
         let s = SantaString::from("aei");
-        assert!(s.contains_three_vowels());
+        assert!(s.satisfies(&MinVowels(3)));
 
         let s = SantaString::from("xazegov");
-        assert!(s.contains_three_vowels());
+        assert!(s.satisfies(&MinVowels(3)));
 
         let s = SantaString::from("aeiouaeiouaeiou");
-        assert!(s.contains_three_vowels());
+        assert!(s.satisfies(&MinVowels(3)));
     }
 
     #[test]
     fn test_has_repeated_letter() {
         let s = SantaString::from("xx");
-        assert!(s.has_repeated_letter());
+        assert!(s.satisfies(&DoubleLetter));
 
         let s = SantaString::from("abcdde");
-        assert!(s.has_repeated_letter());
+        assert!(s.satisfies(&DoubleLetter));
 
         let s = SantaString::from("aabbccdd");
-        assert!(s.has_repeated_letter());
+        assert!(s.satisfies(&DoubleLetter));
     }
 
     #[test]
     fn test_has_no_forbidden_combos() {
         let s = SantaString::from("aei");
-        assert!(s.has_no_forbidden_combos());
+        assert!(s.satisfies(&forbidden_combos()));
 
         let s = SantaString::from("xazegov");
-        assert!(s.has_no_forbidden_combos());
+        assert!(s.satisfies(&forbidden_combos()));
 
         let s = SantaString::from("aeiouaeiouaeiou");
-        assert!(s.has_no_forbidden_combos());
+        assert!(s.satisfies(&forbidden_combos()));
 
         let s = SantaString::from("abcdefg");
-        assert!(!s.has_no_forbidden_combos());
+        assert!(!s.satisfies(&forbidden_combos()));
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules("# comment\n\nsandwich 2  # trailing\nforbid zz").unwrap();
+        assert!(SantaString::from("abcab").satisfies(&rules));
+        assert!(!SantaString::from("abcabzz").satisfies(&rules));
+        assert!(!SantaString::from("abab").satisfies(&rules));
+
+        let rules = parse_rules("double_letter or not (min_vowels 1 and forbid x)").unwrap();
+        assert!(SantaString::from("xx").satisfies(&rules));
+        assert!(SantaString::from("ax").satisfies(&rules));
+        assert!(SantaString::from("bc").satisfies(&rules));
+        assert!(!SantaString::from("ab").satisfies(&rules));
+
+        let rules = parse_rules("repeated_pair overlap").unwrap();
+        assert!(SantaString::from("aaa").satisfies(&rules));
+        assert!(!SantaString::from("aab").satisfies(&rules));
+    }
+
+    #[test]
+    fn test_parse_rules_errors() {
+        let error = |text| parse_rules(text).unwrap_err();
+        assert_eq!(error("double_letter\nvowels 3").line, 2);
+        assert_eq!(
+            error("min_vowels three").message,
+            "min_vowels expects a number, got \"three\""
+        );
+        assert_eq!(error("forbid").message, "bad arguments for forbid: []");
+        assert_eq!(error("(double_letter").message, "missing )");
+        assert_eq!(error("double_letter )").message, "unexpected \")\"");
+        assert_eq!(error("not").message, "expected a rule");
+    }
+
+    #[test]
+    fn test_explain() {
+        use super::rules::Evidence;
+
+        let report = SantaString::from("haegwjzuvuyypxyu").explain(nice_rules());
+        assert!(!report.passed);
+        let failures = report.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].rule, "forbid ab cd pq xy");
+        assert_eq!(
+            failures[0].evidence,
+            Evidence::Found(Some((13, "xy".to_string())))
+        );
+
+        let report = SantaString::from("dvszwmarrgswjxmb").explain(&MinVowels(3));
+        assert_eq!(report.evidence, Evidence::Vowels(vec![(6, 'a')]));
+        assert_eq!(report.to_string(), "min_vowels 3: fail (1 vowels: a@6)");
+
+        let report = SantaString::from("jchzalrnumimnmhp").explain(&DoubleLetter);
+        assert_eq!(report.to_string(), "double_letter: fail (none found)");
+
+        let report = SantaString::from("qjhvhtzxzqqjkmpb").explain(nicer_rules());
+        assert!(report.passed);
+        assert!(report.failures().is_empty());
+        assert_eq!(
+            report.to_string(),
+            "\
+(repeated_pair nonoverlap) and (sandwich 1): pass
+  repeated_pair nonoverlap: pass (\"qj\" at 0 and 10)
+  sandwich 1: pass (\"hvh\" at 2)"
+        );
+    }
+
+    #[test]
+    fn test_explain_naughty() {
+        let text = "ugknbfddgicrmopn\njchzalrnumimnmhp\nhaegwjzuvuyypxyu";
+        assert_eq!(
+            explain_naughty(text, nice_rules()),
+            "\
+line 2: jchzalrnumimnmhp is naughty
+  double_letter: fail (none found)
+line 3: haegwjzuvuyypxyu is naughty
+  forbid ab cd pq xy: fail (\"xy\" at 13)
+"
+        );
+    }
+
+    #[test]
+    fn test_batch_classify() {
+        let text = load("src/day5/input.txt").unwrap();
+        let batch = Batch {
+            chunk_lines: 7,
+            ..Batch::new(vec![nice_rules(), nicer_rules()], 3)
+        };
+
+        let mut partitions: Vec<Partition<Vec<u8>>> = (0..2)
+            .map(|_| Partition {
+                nice: Vec::new(),
+                naughty: Vec::new(),
+            })
+            .collect();
+        let counts = batch.classify(text.as_bytes(), &mut partitions).unwrap();
+        assert_eq!(counts.lines, text.lines().count());
+        assert_eq!(
+            counts.nice,
+            vec![
+                number_of_nice_strings(&text),
+                number_of_nicer_strings(&text)
+            ]
+        );
+
+        // partitions keep input order
+        let nice: Vec<&str> = text
+            .lines()
+            .filter(|s| SantaString::from(s).is_nice())
+            .collect();
+        assert_eq!(
+            String::from_utf8(partitions.remove(0).nice).unwrap(),
+            nice.join("\n") + "\n"
+        );
+        let naughty: Vec<&str> = text
+            .lines()
+            .filter(|s| !SantaString::from(s).is_nicer())
+            .collect();
+        assert_eq!(
+            String::from_utf8(partitions.remove(0).naughty).unwrap(),
+            naughty.join("\n") + "\n"
+        );
+
+        let none: &mut [Partition<Vec<u8>>] = &mut [];
+        let counts = batch.classify("".as_bytes(), none).unwrap();
+        assert_eq!(counts.lines, 0);
+    }
+
+    /// Every string over `alphabet` of exactly `len` characters.
+    fn strings_of_len(alphabet: &[char], len: usize) -> Vec<String> {
+        (0..len).fold(vec![String::new()], |strings, _| {
+            strings
+                .iter()
+                .flat_map(|s| alphabet.iter().map(move |c| format!("{}{}", s, c)))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_count_nice() {
+        let letters: Vec<char> = ('a'..='z').collect();
+        for len in 0..=3 {
+            let strings = strings_of_len(&letters, len);
+            for rules in &[nice_rules(), nicer_rules()] {
+                let expected = strings.iter().filter(|s| rules.matches(s)).count();
+                assert_eq!(Generator::new(rules.machine()).count(len), expected as u128);
+            }
+        }
+
+        // longer strings over fewer letters, with every kind of rule
+        let letters = ['a', 'b', 'c', 'd', 'x', 'y'];
+        let rules = parse_rules(
+            "min_vowels 2 or not sandwich 2\nrepeated_pair overlap\nnot (forbid xy and double_letter)",
+        )
+        .unwrap();
+        for len in 0..=6 {
+            let expected = strings_of_len(&letters, len)
+                .iter()
+                .filter(|s| rules.matches(s))
+                .count();
+            let mut generator = Generator::with_alphabet(rules.machine(), letters.to_vec());
+            assert_eq!(generator.count(len), expected as u128);
+            let mut generator = Generator::with_alphabet(nicer_rules().machine(), letters.to_vec());
+            let expected = strings_of_len(&letters, len)
+                .iter()
+                .filter(|s| SantaString::from(s).is_nicer())
+                .count();
+            assert_eq!(generator.count(len), expected as u128);
+        }
+    }
+
+    #[test]
+    fn test_count_nicer_long() {
+        let mut generator = Generator::new(nicer_rules().machine());
+        assert_eq!(generator.count(16), 2647086280739780243676);
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut rng = Lcg(2015);
+
+        let mut generator = Generator::new(nice_rules().machine());
+        for _ in 0..20 {
+            let s = generator.sample(16, &mut rng).unwrap();
+            assert_eq!(s.chars().count(), 16);
+            assert!(SantaString::from(&s).is_nice(), "{}", s);
+        }
+
+        // naughty only because of the forbidden combos
+        let mut generator = Generator::new(nice_rules().machine_failing(2));
+        for _ in 0..20 {
+            let s = generator.sample(16, &mut rng).unwrap();
+            let report = SantaString::from(&s).explain(nice_rules());
+            let failures: Vec<&str> = report.failures().iter().map(|r| r.rule.as_str()).collect();
+            assert_eq!(failures, vec!["forbid ab cd pq xy"], "{}", s);
+        }
+
+        let mut generator = Generator::new(nicer_rules().machine());
+        assert_eq!(generator.sample(3, &mut rng), None);
+        let s = generator.sample(4, &mut rng).unwrap();
+        assert!(SantaString::from(&s).is_nicer(), "{}", s);
     }
 }
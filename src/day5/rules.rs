@@ -0,0 +1,641 @@
+/*!
+Naughty-or-nice rules and the small language they are written in.
+
+A rule file has one rule expression per line and a string is nice when it
+satisfies every line. Blank lines and text after `#` are ignored.
+
+    min_vowels 3                  at least 3 of a, e, i, o, u
+    double_letter                 a letter twice in a row
+    forbid ab cd pq xy            none of the listed substrings
+    repeated_pair nonoverlap      a pair appearing twice without overlapping
+    repeated_pair overlap         a pair appearing twice, overlaps allowed
+    sandwich 1                    a letter repeating with 1 letter between
+
+Expressions combine with `not`, `and` and `or` (tightest first) and can be
+grouped with parentheses, e.g. `double_letter or (sandwich 1 and not forbid xy)`.
+*/
+
+use super::generate::{self, Machine};
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::iter;
+
+pub trait Rule: fmt::Debug + fmt::Display + Send + Sync {
+    fn matches(&self, s: &str) -> bool;
+
+    /// Like [`Rule::matches`], but also says why.
+    fn explain(&self, s: &str) -> Report;
+
+    /// The rule as a state machine, for counting and generating strings.
+    fn machine(&self) -> Machine;
+}
+
+/// Whether a string passed a rule, and the evidence either way. Positions
+/// count characters from 0.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Report {
+    pub rule: String,
+    pub passed: bool,
+    pub evidence: Evidence,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Evidence {
+    /// The vowels counted, with their positions.
+    Vowels(Vec<(usize, char)>),
+    /// The first matching snippet and its position, if any.
+    Found(Option<(usize, String)>),
+    /// A pair and the two positions it was found at, if any.
+    Pair(Option<(String, usize, usize)>),
+    /// Reports for the parts of a combined rule.
+    Parts(Vec<Report>),
+}
+
+impl Report {
+    fn new<R: Rule + ?Sized>(rule: &R, passed: bool, evidence: Evidence) -> Self {
+        Self {
+            rule: rule.to_string(),
+            passed,
+            evidence,
+        }
+    }
+
+    /// The top-level parts that failed, or this report if it is not combined.
+    pub fn failures(&self) -> Vec<&Report> {
+        match &self.evidence {
+            Evidence::Parts(parts) => parts.iter().filter(|part| !part.passed).collect(),
+            _ if self.passed => Vec::new(),
+            _ => vec![self],
+        }
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let verdict = if self.passed { "pass" } else { "fail" };
+        write!(
+            f,
+            "{:indent$}{}: {}",
+            "",
+            self.rule,
+            verdict,
+            indent = 2 * depth
+        )?;
+        match &self.evidence {
+            Evidence::Vowels(vowels) => {
+                let found: Vec<String> =
+                    vowels.iter().map(|(i, c)| format!("{}@{}", c, i)).collect();
+                write!(f, " ({} vowels: {})", vowels.len(), found.join(", "))
+            }
+            Evidence::Found(Some((i, snippet))) => write!(f, " ({:?} at {})", snippet, i),
+            Evidence::Pair(Some((pair, i, j))) => write!(f, " ({:?} at {} and {})", pair, i, j),
+            Evidence::Found(None) | Evidence::Pair(None) => write!(f, " (none found)"),
+            Evidence::Parts(parts) => parts.iter().try_for_each(|part| {
+                writeln!(f)?;
+                part.write_indented(f, depth + 1)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+// Each check below makes a single pass over the characters of the string, so
+// positions are in characters and any Unicode input is safe. Strings too short
+// for a rule simply fail it (or pass, for `forbid`).
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+#[derive(Debug)]
+pub struct MinVowels(pub usize);
+
+impl Rule for MinVowels {
+    fn matches(&self, s: &str) -> bool {
+        s.chars().filter(|c| is_vowel(*c)).count() >= self.0
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let vowels: Vec<(usize, char)> = s
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| is_vowel(*c))
+            .collect();
+        Report::new(self, vowels.len() >= self.0, Evidence::Vowels(vowels))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::Atom(Box::new(generate::VowelCount(self.0)))
+    }
+}
+
+#[derive(Debug)]
+pub struct DoubleLetter;
+
+impl Rule for DoubleLetter {
+    fn matches(&self, s: &str) -> bool {
+        Sandwich(0).matches(s)
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let found = Sandwich(0).find(s);
+        Report::new(self, found.is_some(), Evidence::Found(found))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::Atom(Box::new(generate::Sandwich(0)))
+    }
+}
+
+#[derive(Debug)]
+pub struct Forbid(pub Vec<String>);
+
+impl Forbid {
+    /// The first forbidden substring, by position.
+    fn find(&self, s: &str) -> Option<(usize, String)> {
+        s.char_indices().enumerate().find_map(|(pos, (i, _))| {
+            self.0
+                .iter()
+                .find(|combo| s[i..].starts_with(combo.as_str()))
+                .map(|combo| (pos, combo.clone()))
+        })
+    }
+}
+
+impl Rule for Forbid {
+    fn matches(&self, s: &str) -> bool {
+        self.find(s).is_none()
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let found = self.find(s);
+        Report::new(self, found.is_none(), Evidence::Found(found))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::Atom(Box::new(generate::Forbid(
+            self.0.iter().map(|combo| combo.chars().collect()).collect(),
+        )))
+    }
+}
+
+#[derive(Debug)]
+pub struct RepeatedPair {
+    pub overlap: bool,
+}
+
+impl RepeatedPair {
+    /// The first pair to repeat, remembering only where each pair was first
+    /// seen since that is the occurrence least likely to overlap.
+    fn find(&self, s: &str) -> Option<(String, usize, usize)> {
+        let gap = if self.overlap { 1 } else { 2 };
+        let mut first_seen = HashMap::new();
+        let mut prev = None;
+        for (i, c) in s.chars().enumerate() {
+            if let Some(p) = prev {
+                let start = i - 1;
+                let first = *first_seen.entry((p, c)).or_insert(start);
+                if start - first >= gap {
+                    return Some(([p, c].iter().collect(), first, start));
+                }
+            }
+            prev = Some(c);
+        }
+        None
+    }
+}
+
+impl Rule for RepeatedPair {
+    fn matches(&self, s: &str) -> bool {
+        self.find(s).is_some()
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let found = self.find(s);
+        Report::new(self, found.is_some(), Evidence::Pair(found))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::Atom(Box::new(generate::RepeatedPair {
+            overlap: self.overlap,
+        }))
+    }
+}
+
+/// A letter that repeats with exactly this many letters between.
+#[derive(Debug)]
+pub struct Sandwich(pub usize);
+
+impl Sandwich {
+    fn find(&self, s: &str) -> Option<(usize, String)> {
+        let mut recent = VecDeque::with_capacity(self.0 + 1);
+        for (i, c) in s.chars().enumerate() {
+            if recent.len() == self.0 + 1 {
+                if recent[0] == c {
+                    let snippet = recent.iter().chain(iter::once(&c)).collect();
+                    return Some((i - self.0 - 1, snippet));
+                }
+                recent.pop_front();
+            }
+            recent.push_back(c);
+        }
+        None
+    }
+}
+
+impl Rule for Sandwich {
+    fn matches(&self, s: &str) -> bool {
+        self.find(s).is_some()
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let found = self.find(s);
+        Report::new(self, found.is_some(), Evidence::Found(found))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::Atom(Box::new(generate::Sandwich(self.0)))
+    }
+}
+
+#[derive(Debug)]
+pub struct All(pub Vec<Box<dyn Rule>>);
+
+impl All {
+    /// A machine for strings that fail part `failing` and pass all the others.
+    pub fn machine_failing(&self, failing: usize) -> Machine {
+        Machine::All(
+            self.0
+                .iter()
+                .enumerate()
+                .map(|(i, rule)| match i {
+                    _ if i == failing => Machine::Not(Box::new(rule.machine())),
+                    _ => rule.machine(),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Rule for All {
+    fn matches(&self, s: &str) -> bool {
+        self.0.iter().all(|rule| rule.matches(s))
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let parts: Vec<Report> = self.0.iter().map(|rule| rule.explain(s)).collect();
+        let passed = parts.iter().all(|part| part.passed);
+        Report::new(self, passed, Evidence::Parts(parts))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::All(self.0.iter().map(|rule| rule.machine()).collect())
+    }
+}
+
+#[derive(Debug)]
+pub struct Any(pub Vec<Box<dyn Rule>>);
+
+impl Rule for Any {
+    fn matches(&self, s: &str) -> bool {
+        self.0.iter().any(|rule| rule.matches(s))
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let parts: Vec<Report> = self.0.iter().map(|rule| rule.explain(s)).collect();
+        let passed = parts.iter().any(|part| part.passed);
+        Report::new(self, passed, Evidence::Parts(parts))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::Any(self.0.iter().map(|rule| rule.machine()).collect())
+    }
+}
+
+#[derive(Debug)]
+pub struct Not(pub Box<dyn Rule>);
+
+impl Rule for Not {
+    fn matches(&self, s: &str) -> bool {
+        !self.0.matches(s)
+    }
+
+    fn explain(&self, s: &str) -> Report {
+        let inner = self.0.explain(s);
+        Report::new(self, !inner.passed, Evidence::Parts(vec![inner]))
+    }
+
+    fn machine(&self) -> Machine {
+        Machine::Not(Box::new(self.0.machine()))
+    }
+}
+
+impl fmt::Display for MinVowels {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "min_vowels {}", self.0)
+    }
+}
+
+impl fmt::Display for DoubleLetter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "double_letter")
+    }
+}
+
+impl fmt::Display for Forbid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "forbid {}", self.0.join(" "))
+    }
+}
+
+impl fmt::Display for RepeatedPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.overlap {
+            true => write!(f, "repeated_pair overlap"),
+            false => write!(f, "repeated_pair nonoverlap"),
+        }
+    }
+}
+
+impl fmt::Display for Sandwich {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sandwich {}", self.0)
+    }
+}
+
+/// Writes combined rules in parentheses so the output parses back the same.
+fn write_joined(f: &mut fmt::Formatter, rules: &[Box<dyn Rule>], op: &str) -> fmt::Result {
+    let parts: Vec<String> = rules.iter().map(|rule| format!("({})", rule)).collect();
+    write!(f, "{}", parts.join(op))
+}
+
+impl fmt::Display for All {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_joined(f, &self.0, " and ")
+    }
+}
+
+impl fmt::Display for Any {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_joined(f, &self.0, " or ")
+    }
+}
+
+impl fmt::Display for Not {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "not ({})", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parses a rule file into the conjunction of its lines.
+pub fn parse_rules(text: &str) -> Result<All, ParseError> {
+    let mut rules = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let tokens = tokenize(line);
+        if tokens.is_empty() {
+            continue;
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let rule = parser
+            .expression()
+            .and_then(|rule| match parser.peek() {
+                None => Ok(rule),
+                Some(token) => Err(format!("unexpected {:?}", token)),
+            })
+            .map_err(|message| ParseError {
+                line: i + 1,
+                message,
+            })?;
+        rules.push(rule);
+    }
+    Ok(All(rules))
+}
+
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(s) = start.take() {
+                tokens.push(&line[s..i]);
+            }
+            if !c.is_whitespace() {
+                tokens.push(&line[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&line[s..]);
+    }
+    tokens
+}
+
+const KEYWORDS: [&str; 5] = ["and", "or", "not", "(", ")"];
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn expression(&mut self) -> Result<Box<dyn Rule>, String> {
+        let mut terms = vec![self.term()?];
+        while self.peek() == Some("or") {
+            self.next();
+            terms.push(self.term()?);
+        }
+        Ok(match terms.len() {
+            1 => terms.pop().unwrap(),
+            _ => Box::new(Any(terms)),
+        })
+    }
+
+    fn term(&mut self) -> Result<Box<dyn Rule>, String> {
+        let mut factors = vec![self.factor()?];
+        while self.peek() == Some("and") {
+            self.next();
+            factors.push(self.factor()?);
+        }
+        Ok(match factors.len() {
+            1 => factors.pop().unwrap(),
+            _ => Box::new(All(factors)),
+        })
+    }
+
+    fn factor(&mut self) -> Result<Box<dyn Rule>, String> {
+        match self.next() {
+            Some("not") => Ok(Box::new(Not(self.factor()?))),
+            Some("(") => {
+                let rule = self.expression()?;
+                match self.next() {
+                    Some(")") => Ok(rule),
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            Some(name) if !KEYWORDS.contains(&name) => {
+                let mut args = Vec::new();
+                while let Some(arg) = self.peek().filter(|token| !KEYWORDS.contains(token)) {
+                    args.push(arg);
+                    self.next();
+                }
+                atom(name, &args)
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("expected a rule".to_string()),
+        }
+    }
+}
+
+fn atom(name: &str, args: &[&str]) -> Result<Box<dyn Rule>, String> {
+    let count = || match args {
+        [n] => n
+            .parse()
+            .map_err(|_| format!("{} expects a number, got {:?}", name, n)),
+        _ => Err(format!("{} expects one number", name)),
+    };
+    match (name, args) {
+        ("min_vowels", _) => Ok(Box::new(MinVowels(count()?))),
+        ("sandwich", _) => Ok(Box::new(Sandwich(count()?))),
+        ("double_letter", []) => Ok(Box::new(DoubleLetter)),
+        ("forbid", [_, ..]) => Ok(Box::new(Forbid(
+            args.iter().map(|arg| arg.to_string()).collect(),
+        ))),
+        ("repeated_pair", []) | ("repeated_pair", ["nonoverlap"]) => {
+            Ok(Box::new(RepeatedPair { overlap: false }))
+        }
+        ("repeated_pair", ["overlap"]) => Ok(Box::new(RepeatedPair { overlap: true })),
+        ("double_letter", _) | ("forbid", _) | ("repeated_pair", _) => {
+            Err(format!("bad arguments for {}: {:?}", name, args))
+        }
+        _ => Err(format!("unknown rule {:?}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every string over `alphabet` up to `max_len` characters long.
+    fn all_strings(alphabet: &[char], max_len: usize) -> Vec<String> {
+        let mut strings = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..max_len {
+            last = last
+                .iter()
+                .flat_map(|s| alphabet.iter().map(move |c| format!("{}{}", s, c)))
+                .collect();
+            strings.extend(last.iter().cloned());
+        }
+        strings
+    }
+
+    // Brute-force oracles straight from the puzzle's wording.
+
+    fn oracle_sandwich(chars: &[char], between: usize) -> Option<usize> {
+        (0..chars.len())
+            .find(|i| i + between + 1 < chars.len() && chars[*i] == chars[i + between + 1])
+    }
+
+    fn oracle_repeated_pair(chars: &[char], gap: usize) -> bool {
+        (0..chars.len()).any(|i| {
+            (i + gap..chars.len())
+                .any(|j| j + 1 < chars.len() && chars[i..i + 2] == chars[j..j + 2])
+        })
+    }
+
+    fn oracle_forbid(chars: &[char], combos: &[&str]) -> Option<usize> {
+        (0..chars.len()).find(|i| {
+            combos.iter().any(|combo| {
+                let combo: Vec<char> = combo.chars().collect();
+                chars[*i..].starts_with(&combo)
+            })
+        })
+    }
+
+    #[test]
+    fn test_rules_against_oracle() {
+        let combos = ["ab", "éa", "b"];
+        let forbid = Forbid(combos.iter().map(|s| s.to_string()).collect());
+
+        for s in all_strings(&['a', 'b', 'é'], 7) {
+            let chars: Vec<char> = s.chars().collect();
+
+            let vowels = chars.iter().filter(|c| **c == 'a').count();
+            for n in 0..4 {
+                assert_eq!(MinVowels(n).matches(&s), vowels >= n, "{:?}", s);
+            }
+
+            for between in 0..3 {
+                let found = Sandwich(between).find(&s).map(|(i, _)| i);
+                assert_eq!(found, oracle_sandwich(&chars, between), "{:?}", s);
+            }
+            assert_eq!(
+                DoubleLetter.matches(&s),
+                oracle_sandwich(&chars, 0).is_some()
+            );
+
+            for (overlap, gap) in [(true, 1), (false, 2)] {
+                let rule = RepeatedPair { overlap };
+                assert_eq!(
+                    rule.matches(&s),
+                    oracle_repeated_pair(&chars, gap),
+                    "{:?}",
+                    s
+                );
+                if let Some((pair, i, j)) = rule.find(&s) {
+                    let pair: Vec<char> = pair.chars().collect();
+                    assert!(j >= i + gap);
+                    assert_eq!(chars[i..i + 2], pair[..]);
+                    assert_eq!(chars[j..j + 2], pair[..]);
+                }
+            }
+
+            let found = forbid.find(&s).map(|(i, _)| i);
+            assert_eq!(found, oracle_forbid(&chars, &combos), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_rules_on_short_strings() {
+        for s in &["", "a", "é"] {
+            assert!(!DoubleLetter.matches(s));
+            assert!(!Sandwich(1).matches(s));
+            assert!(!RepeatedPair { overlap: true }.matches(s));
+            assert!(!RepeatedPair { overlap: false }.matches(s));
+            assert!(Forbid(vec!["ab".to_string()]).matches(s));
+            assert!(MinVowels(0).matches(s));
+        }
+        assert!(!MinVowels(1).matches(""));
+        assert!(!RepeatedPair { overlap: false }.matches("ééé"));
+        assert!(RepeatedPair { overlap: false }.matches("éééé"));
+    }
+}
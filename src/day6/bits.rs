@@ -0,0 +1,70 @@
+/*!
+An on/off grid with one bit per light.
+
+Each row of lights is packed into `u64` words. A rectangle covers the same run
+of words in every row it touches, so its mask is built once and then ORed,
+AND-NOTed or XORed into each row, which the compiler can vectorise.
+*/
+
+use super::{check_bounds, Instruction, LightAction, OutOfBounds};
+
+pub struct BitLights {
+    width: usize,
+    height: usize,
+    /// Words per row.
+    stride: usize,
+    words: Vec<u64>,
+}
+
+impl BitLights {
+    pub fn new(width: usize, height: usize) -> Self {
+        let stride = height.div_ceil(64);
+        Self {
+            width,
+            height,
+            stride,
+            words: vec![0; width * stride],
+        }
+    }
+
+    pub fn num_lit_lights(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn do_instruction(&mut self, instruction: Instruction) {
+        let (c1, c2) = instruction.coordinates;
+        let (first, last) = (c1.1 / 64, c2.1 / 64);
+        let masks: Vec<u64> = (first..=last)
+            .map(|w| {
+                let low = c1.1.max(w * 64) - w * 64;
+                let high = c2.1.min(w * 64 + 63) - w * 64;
+                (u64::MAX >> (63 - high)) & (u64::MAX << low)
+            })
+            .collect();
+
+        for x in c1.0..=c2.0 {
+            let start = x * self.stride;
+            let row = &mut self.words[start + first..=start + last];
+            let words = row.iter_mut().zip(&masks);
+            match instruction.action {
+                LightAction::TurnOn => words.for_each(|(word, mask)| *word |= mask),
+                LightAction::TurnOff => words.for_each(|(word, mask)| *word &= !mask),
+                LightAction::Toggle => words.for_each(|(word, mask)| *word ^= mask),
+                LightAction::Set(0) => words.for_each(|(word, mask)| *word &= !mask),
+                LightAction::Set(_) => words.for_each(|(word, mask)| *word |= mask),
+            }
+        }
+    }
+
+    /// Follows every instruction, or none if any of them is out of bounds.
+    pub fn do_instructions(&mut self, instructions: &[Instruction]) -> Result<(), OutOfBounds> {
+        check_bounds(instructions, self.width, self.height)?;
+        instructions
+            .iter()
+            .for_each(|instruction| self.do_instruction(*instruction));
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+/*!
+A light grid that only stores as many cells as the instructions can tell apart.
+
+Every rectangle edge in the instruction list splits the grid, and the lights
+between neighbouring splits always change together. Storing one cell per block,
+weighted by its area when scoring, makes the cost depend on the number of
+instructions rather than the size of the grid.
+*/
+
+use super::model::LightModel;
+use super::Instruction;
+
+pub struct CompressedLights<M: LightModel> {
+    model: M,
+    /// Sorted first coordinates where a new block starts, ending with one
+    /// past the last light.
+    xs: Vec<usize>,
+    ys: Vec<usize>,
+    /// The state of each block, row by row in `xs`.
+    blocks: Vec<M::Cell>,
+}
+
+impl<M: LightModel> CompressedLights<M> {
+    /// A grid split wherever any of `instructions` could need it to be. Every
+    /// light they cover must be below `usize::MAX` on both axes.
+    pub fn new(model: M, instructions: &[Instruction]) -> Self {
+        let mut xs = vec![0];
+        let mut ys = vec![0];
+        for instruction in instructions {
+            let (c1, c2) = instruction.coordinates;
+            xs.extend([c1.0, c2.0 + 1]);
+            ys.extend([c1.1, c2.1 + 1]);
+        }
+        for bounds in [&mut xs, &mut ys] {
+            bounds.sort_unstable();
+            bounds.dedup();
+        }
+        let blocks = vec![M::Cell::default(); (xs.len() - 1) * (ys.len() - 1)];
+        Self {
+            model,
+            xs,
+            ys,
+            blocks,
+        }
+    }
+
+    /// The model's score of every light, counting each block once per light
+    /// in it.
+    pub fn score(&self) -> usize {
+        let width = self.ys.len() - 1;
+        self.xs
+            .windows(2)
+            .enumerate()
+            .map(|(i, x)| {
+                let row = &self.blocks[i * width..(i + 1) * width];
+                let score: usize = self
+                    .ys
+                    .windows(2)
+                    .zip(row)
+                    .map(|(y, cell)| self.model.score(*cell) * (y[1] - y[0]))
+                    .sum();
+                score * (x[1] - x[0])
+            })
+            .sum()
+    }
+
+    /// Follows an instruction, whose rectangle must have been passed to `new`.
+    pub fn do_instruction(&mut self, instruction: Instruction) {
+        let (c1, c2) = instruction.coordinates;
+        let (x1, x2) = (block(&self.xs, c1.0), block(&self.xs, c2.0 + 1));
+        let (y1, y2) = (block(&self.ys, c1.1), block(&self.ys, c2.1 + 1));
+        let width = self.ys.len() - 1;
+        for i in x1..x2 {
+            for cell in &mut self.blocks[i * width + y1..i * width + y2] {
+                self.model.apply(instruction.action, cell);
+            }
+        }
+    }
+
+    pub fn do_instructions(&mut self, instructions: &[Instruction]) {
+        instructions
+            .iter()
+            .for_each(|instruction| self.do_instruction(*instruction));
+    }
+}
+
+fn block(bounds: &[usize], coordinate: usize) -> usize {
+    bounds
+        .binary_search(&coordinate)
+        .expect("coordinate was not split on")
+}
@@ -0,0 +1,95 @@
+/*!
+An undo log for a light grid.
+
+Statements are kept after they are applied, so the grid can be rewound to how
+it looked after any of them and replayed forward again. Rewinding starts from
+the nearest snapshot of the grid rather than from the very beginning.
+*/
+
+use super::model::LightModel;
+use super::script::{check_statements, Statement};
+use super::{Coordinate, Lights, OutOfBounds};
+
+/// Statements between snapshots of the grid.
+const SNAPSHOT_EVERY: usize = 32;
+
+pub struct History<M: LightModel> {
+    lights: Lights<M>,
+    statements: Vec<Statement>,
+    /// How many statements the grid currently reflects.
+    position: usize,
+    /// The grid after every `SNAPSHOT_EVERY` statements, from the start.
+    snapshots: Vec<Vec<Vec<M::Cell>>>,
+}
+
+impl<M: LightModel> History<M> {
+    pub fn new(lights: Lights<M>) -> Self {
+        let snapshots = vec![lights.grid.clone()];
+        Self {
+            lights,
+            statements: Vec::new(),
+            position: 0,
+            snapshots,
+        }
+    }
+
+    pub fn lights(&self) -> &Lights<M> {
+        &self.lights
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    /// Applies a statement, forgetting any that were undone before it.
+    pub fn push(&mut self, statement: Statement) -> Result<(), OutOfBounds> {
+        check_statements(
+            std::slice::from_ref(&statement),
+            self.lights.width,
+            self.lights.height,
+        )?;
+        self.statements.truncate(self.position);
+        self.snapshots.truncate(self.position / SNAPSHOT_EVERY + 1);
+        self.statements.push(statement);
+        self.step();
+        Ok(())
+    }
+
+    /// Rewinds or replays until the grid reflects the first `position`
+    /// statements.
+    pub fn seek(&mut self, position: usize) {
+        assert!(
+            position <= self.statements.len(),
+            "no statement {}",
+            position
+        );
+        if position < self.position {
+            let snapshot = position / SNAPSHOT_EVERY;
+            self.lights.grid.clone_from(&self.snapshots[snapshot]);
+            self.position = snapshot * SNAPSHOT_EVERY;
+        }
+        while self.position < position {
+            self.step();
+        }
+    }
+
+    /// The latest applied statement whose shape covers the light.
+    pub fn last_affecting(&self, light: Coordinate) -> Option<&Statement> {
+        self.statements[..self.position]
+            .iter()
+            .rev()
+            .find(|statement| statement.shape.contains(light))
+    }
+
+    fn step(&mut self) {
+        for instruction in self.statements[self.position].instructions() {
+            self.lights.do_instruction(instruction);
+        }
+        self.position += 1;
+        if self.position.is_multiple_of(SNAPSHOT_EVERY)
+            && self.snapshots.len() == self.position / SNAPSHOT_EVERY
+        {
+            self.snapshots.push(self.lights.grid.clone());
+        }
+    }
+}
@@ -0,0 +1,150 @@
+/*!
+Netpbm pictures of the light grid.
+
+Each light is one pixel, with x running left to right and y top to bottom. A
+light's level is its model's score: PBM images show any light above zero as
+white, while PGM and PPM images scale levels so that a chosen maximum is full
+brightness.
+*/
+
+use super::model::LightModel;
+use super::script::{check_statements, Statement};
+use super::{invalid_data, Lights};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Black and white.
+    Pbm,
+    /// Grayscale.
+    Pgm,
+    /// Colour, in the warm white of an incandescent bulb.
+    Ppm,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Pbm => "pbm",
+            Format::Pgm => "pgm",
+            Format::Ppm => "ppm",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pbm" => Ok(Format::Pbm),
+            "pgm" => Ok(Format::Pgm),
+            "ppm" => Ok(Format::Ppm),
+            _ => Err(format!("unknown image format {:?}", s)),
+        }
+    }
+}
+
+const BULB: [usize; 3] = [255, 214, 170];
+
+impl<M: LightModel> Lights<M> {
+    /// The highest level of any light.
+    pub fn max_level(&self) -> usize {
+        self.grid
+            .iter()
+            .flatten()
+            .map(|cell| self.model.score(*cell))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Writes the grid as a binary Netpbm image, with lights at level `max`
+    /// or above at full brightness.
+    pub fn write_image<W: Write>(&self, format: Format, max: usize, mut out: W) -> io::Result<()> {
+        let max = max.max(1);
+        let level = |x: usize, y: usize| self.model.score(self.grid[x][y]).min(max);
+        let scale = |level: usize, full: usize| (level * full / max) as u8;
+
+        match format {
+            Format::Pbm => {
+                writeln!(out, "P4\n{} {}", self.width, self.height)?;
+                // rows are padded to whole bytes, and 1 is black
+                let mut row = vec![0; self.width.div_ceil(8)];
+                for y in 0..self.height {
+                    row.iter_mut().for_each(|byte| *byte = 0);
+                    for x in 0..self.width {
+                        if level(x, y) == 0 {
+                            row[x / 8] |= 0x80 >> (x % 8);
+                        }
+                    }
+                    out.write_all(&row)?;
+                }
+            }
+            Format::Pgm => {
+                writeln!(out, "P5\n{} {}\n255", self.width, self.height)?;
+                for y in 0..self.height {
+                    let row: Vec<u8> = (0..self.width).map(|x| scale(level(x, y), 255)).collect();
+                    out.write_all(&row)?;
+                }
+            }
+            Format::Ppm => {
+                writeln!(out, "P6\n{} {}\n255", self.width, self.height)?;
+                for y in 0..self.height {
+                    let row: Vec<u8> = (0..self.width)
+                        .flat_map(|x| BULB.map(|full| scale(level(x, y), full)))
+                        .collect();
+                    out.write_all(&row)?;
+                }
+            }
+        }
+        out.flush()
+    }
+
+    pub fn save_image<P: AsRef<Path>>(
+        &self,
+        format: Format,
+        max: usize,
+        path: P,
+    ) -> io::Result<()> {
+        self.write_image(format, max, BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Writes `<name>-0000.<ext>` with every light off, then one frame after
+/// each statement, all scaled to the brightest level any light reaches.
+pub fn save_frames<M: LightModel + Copy>(
+    model: M,
+    (width, height): (usize, usize),
+    statements: &[Statement],
+    format: Format,
+    dir: &Path,
+    name: &str,
+) -> io::Result<()> {
+    check_statements(statements, width, height).map_err(invalid_data)?;
+    let follow = |lights: &mut Lights<M>, statement: &Statement| {
+        for instruction in statement.instructions() {
+            lights.do_instruction(instruction);
+        }
+    };
+
+    // first pass to find the scale, so frames do not flicker as it changes
+    let mut lights = Lights::new(model, width, height);
+    let mut max = 0;
+    for statement in statements {
+        follow(&mut lights, statement);
+        max = max.max(lights.max_level());
+    }
+
+    let mut lights = Lights::new(model, width, height);
+    let path = |i: usize| dir.join(format!("{}-{:04}.{}", name, i, format.extension()));
+    lights.save_image(format, max, path(0))?;
+    for (i, statement) in statements.iter().enumerate() {
+        follow(&mut lights, statement);
+        lights.save_image(format, max, path(i + 1))?;
+    }
+    Ok(())
+}
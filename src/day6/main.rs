@@ -37,29 +37,221 @@ For example:
     toggle 0,0 through 999,999 would increase the total brightness by 2000000.
 */
 
-use util::load;
-
+mod bits;
+mod compressed;
+mod history;
+mod image;
+mod model;
+mod script;
+
+use bits::BitLights;
+use compressed::CompressedLights;
+use history::History;
+use image::{save_frames, Format};
+use model::{Binary, Capped, Dimmer, LightModel};
+use script::{check_statements, parse_script, Statement};
+
+use util::{flag_value, invalid_data, load, parse_flag, positional};
+
+use std::env;
+use std::fmt;
 use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 fn main() -> io::Result<()> {
-    let contents = load("src/day6/input.txt")?;
-
-    let instructions = contents
-        .lines()
-        .map(|line| Instruction::from(line))
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = positional(&args, &VALUE_FLAGS)
+        .first()
+        .copied()
+        .unwrap_or("src/day6/input.txt");
+    let contents = load(path)?;
+    let statements = parse_script(&contents).map_err(invalid_data)?;
+
+    let cap = match flag_value(&args, "--cap") {
+        Some(cap) => Some(parse_flag("--cap", cap)?),
+        None => None,
+    };
+
+    let size = if args.iter().any(|arg| arg == "--compressed") {
+        None
+    } else {
+        let width = match flag_value(&args, "--width") {
+            Some(width) => parse_flag("--width", width)?,
+            None => GRID_DIM,
+        };
+        let height = match flag_value(&args, "--height") {
+            Some(height) => parse_flag("--height", height)?,
+            None => GRID_DIM,
+        };
+        Some((width, height))
+    };
+
+    // checked before drawing, to report the line a shape came from; the
+    // compressed grid splits one past each light, so that must fit too
+    let (width, height) = size.unwrap_or((usize::MAX, usize::MAX));
+    check_statements(&statements, width, height).map_err(invalid_data)?;
+    let instructions: Vec<Instruction> = statements
+        .iter()
+        .flat_map(Statement::instructions)
         .collect();
 
-    let mut lights = Lights::new();
-    lights.do_instructions(&instructions);
-    println!("part1: {}", lights.num_lit_lights());
+    if args.iter().any(|arg| arg == "--bench") {
+        return bench(&instructions, size.unwrap_or((GRID_DIM, GRID_DIM)));
+    }
+
+    let part1 = score(Binary, &instructions, size).map_err(invalid_data)?;
+    println!("part1: {}", part1);
+    let part2 = score(Dimmer, &instructions, size).map_err(invalid_data)?;
+    println!("part2: {}", part2);
+    if let Some(cap) = cap {
+        let capped = score(Capped(cap), &instructions, size).map_err(invalid_data)?;
+        println!("capped at {}: {}", cap, capped);
+    }
+
+    let last = match flag_value(&args, "--last") {
+        Some(light) => Some(parse_flag::<Coordinate>("--last", light)?),
+        None => None,
+    };
+    let rewind = match flag_value(&args, "--rewind") {
+        Some(n) => Some(parse_flag("--rewind", n)?),
+        None => None,
+    };
+    if last.is_some() || rewind.is_some() {
+        let (width, height) = size.unwrap_or((GRID_DIM, GRID_DIM));
+        let mut history = History::new(Lights::new(Binary, width, height));
+        for statement in statements.iter().cloned() {
+            history.push(statement).map_err(invalid_data)?;
+        }
+        if let Some(n) = rewind {
+            if n > history.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("only {} statements to rewind", history.len()),
+                ));
+            }
+            history.seek(n);
+            println!("after {}: {} lit", n, history.lights().score());
+        }
+        if let Some(light) = last {
+            match history.last_affecting(light) {
+                Some(statement) => println!(
+                    "{} was last changed by line {}: {}",
+                    light, statement.line, statement
+                ),
+                None => println!("{} was never changed", light),
+            }
+        }
+    }
+
+    if let Some(dir) = flag_value(&args, "--image") {
+        let size = size.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--image draws the full grid and cannot be used with --compressed",
+            )
+        })?;
+        let format = match flag_value(&args, "--format") {
+            Some(format) => Some(parse_flag("--format", format)?),
+            None => None,
+        };
+        let frames = args.iter().any(|arg| arg == "--frames");
+        let dir = Path::new(dir);
+        let binary = format.unwrap_or(Format::Pbm);
+        let gray = format.unwrap_or(Format::Pgm);
+        render(Binary, "part1", binary, &statements, size, dir, frames)?;
+        render(Dimmer, "part2", gray, &statements, size, dir, frames)?;
+        if let Some(cap) = cap {
+            render(Capped(cap), "capped", gray, &statements, size, dir, frames)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Follows the instructions on a `width` by `height` grid, or on a compressed
+/// grid of any size when there is no `size`.
+fn score<M: LightModel>(
+    model: M,
+    instructions: &[Instruction],
+    size: Option<(usize, usize)>,
+) -> Result<usize, OutOfBounds> {
+    match size {
+        Some((width, height)) => {
+            let mut lights = Lights::new(model, width, height);
+            lights.do_instructions(instructions)?;
+            Ok(lights.score())
+        }
+        None => {
+            let mut lights = CompressedLights::new(model, instructions);
+            lights.do_instructions(instructions);
+            Ok(lights.score())
+        }
+    }
+}
 
-    let mut lights = Lights::new();
-    lights.do_new_instructions(&instructions);
-    println!("part2: {}", lights.total_brightness());
+/// Times part 1 on each kind of grid, taking the best of a few runs.
+fn bench(instructions: &[Instruction], (width, height): (usize, usize)) -> io::Result<()> {
+    check_bounds(instructions, width, height).map_err(invalid_data)?;
+
+    let time = |name: &str, run: &dyn Fn() -> usize| {
+        let mut best = Duration::MAX;
+        let mut lit = 0;
+        for _ in 0..5 {
+            let start = Instant::now();
+            lit = run();
+            best = best.min(start.elapsed());
+        }
+        println!("{:>10}: {} lit in {:?}", name, lit, best);
+    };
+
+    time("grid", &|| {
+        let mut lights = Lights::new(Binary, width, height);
+        lights.do_instructions(instructions).unwrap();
+        lights.score()
+    });
+    time("bits", &|| {
+        let mut lights = BitLights::new(width, height);
+        lights.do_instructions(instructions).unwrap();
+        lights.num_lit_lights()
+    });
+    time("compressed", &|| {
+        let mut lights = CompressedLights::new(Binary, instructions);
+        lights.do_instructions(instructions);
+        lights.score()
+    });
 
     Ok(())
 }
 
+/// Writes `<dir>/<name>.<ext>`, or with `frames` one image per statement.
+fn render<M: LightModel + Copy>(
+    model: M,
+    name: &str,
+    format: Format,
+    statements: &[Statement],
+    (width, height): (usize, usize),
+    dir: &Path,
+    frames: bool,
+) -> io::Result<()> {
+    if frames {
+        return save_frames(model, (width, height), statements, format, dir, name);
+    }
+    let mut lights = Lights::new(model, width, height);
+    for statement in statements {
+        lights
+            .do_instructions(&statement.instructions())
+            .map_err(invalid_data)?;
+    }
+    let path = dir.join(format!("{}.{}", name, format.extension()));
+    lights.save_image(format, lights.max_level(), path)
+}
+
+const VALUE_FLAGS: [&str; 7] = [
+    "--width", "--height", "--cap", "--image", "--format", "--last", "--rewind",
+];
+
 const GRID_DIM: usize = 1000;
 
 type Brightness = usize;
@@ -68,90 +260,127 @@ type Brightness = usize;
 struct Coordinate(usize, usize);
 
 impl Coordinate {
+    #[cfg(test)]
     fn from(s: &str) -> Self {
-        let comma = s.find(",").expect("no comma in coordinate");
-        let first = (&s[..comma])
-            .parse()
-            .expect("first coordinate parse failed");
-        let second = (&s[comma + 1..])
-            .parse()
-            .expect("first coordinate parse failed");
-        Self(first, second)
+        s.parse().unwrap()
+    }
+}
+
+impl FromStr for Coordinate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("expected a coordinate like 3,4, found {:?}", s);
+        let (first, second) = s.split_once(',').ok_or_else(bad)?;
+        Ok(Self(
+            first.parse().map_err(|_| bad())?,
+            second.parse().map_err(|_| bad())?,
+        ))
+    }
+}
+
+impl fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.0, self.1)
     }
 }
 
 type CoordinatePair = (Coordinate, Coordinate);
 
-struct Lights {
-    grid: Vec<Vec<Brightness>>,
+/// The corners of the same rectangle, smallest first.
+fn normalise(c1: Coordinate, c2: Coordinate) -> CoordinatePair {
+    (
+        Coordinate(c1.0.min(c2.0), c1.1.min(c2.1)),
+        Coordinate(c1.0.max(c2.0), c1.1.max(c2.1)),
+    )
+}
+
+/// An instruction whose shape does not fit on the grid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct OutOfBounds {
+    /// Counting from 1 in the script, or in the instructions given.
+    line: usize,
+    text: String,
+    width: usize,
+    height: usize,
 }
 
-impl Lights {
-    fn new() -> Self {
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {:?} is outside the {}x{} grid",
+            self.line, self.text, self.width, self.height
+        )
+    }
+}
+
+/// Finds the first instruction that reaches past the edge of a grid.
+fn check_bounds(
+    instructions: &[Instruction],
+    width: usize,
+    height: usize,
+) -> Result<(), OutOfBounds> {
+    let position = instructions.iter().position(|instruction| {
+        let (_, c2) = instruction.coordinates;
+        c2.0 >= width || c2.1 >= height
+    });
+    match position {
+        Some(i) => Err(OutOfBounds {
+            line: i + 1,
+            text: instructions[i].to_string(),
+            width,
+            height,
+        }),
+        None => Ok(()),
+    }
+}
+
+struct Lights<M: LightModel> {
+    model: M,
+    width: usize,
+    height: usize,
+    grid: Vec<Vec<M::Cell>>,
+}
+
+impl<M: LightModel> Lights<M> {
+    fn new(model: M, width: usize, height: usize) -> Self {
         Self {
-            grid: vec![vec![0; GRID_DIM]; GRID_DIM],
+            model,
+            width,
+            height,
+            grid: vec![vec![M::Cell::default(); height]; width],
         }
     }
 
-    fn total_brightness(&self) -> Brightness {
+    fn check(&self, instructions: &[Instruction]) -> Result<(), OutOfBounds> {
+        check_bounds(instructions, self.width, self.height)
+    }
+
+    fn score(&self) -> usize {
         self.grid
             .iter()
-            .map(|row| {
-                let brightness: usize = row.iter().sum();
-                brightness
-            })
+            .flatten()
+            .map(|cell| self.model.score(*cell))
             .sum()
     }
 
     fn do_instruction(&mut self, instruction: Instruction) {
         let (c1, c2) = instruction.coordinates;
-        for i in c1.0..=c2.0 {
-            for j in c1.1..=c2.1 {
-                match instruction.action {
-                    LightAction::TurnOff => self.grid[i][j] = 0,
-                    LightAction::TurnOn => self.grid[i][j] = 1,
-                    LightAction::Toggle => self.grid[i][j] ^= 1,
-                }
+        for row in &mut self.grid[c1.0..=c2.0] {
+            for cell in &mut row[c1.1..=c2.1] {
+                self.model.apply(instruction.action, cell);
             }
         }
     }
 
-    fn do_instructions(&mut self, instructions: &Vec<Instruction>) {
+    /// Follows every instruction, or none if any of them is out of bounds.
+    fn do_instructions(&mut self, instructions: &[Instruction]) -> Result<(), OutOfBounds> {
+        self.check(instructions)?;
         instructions
             .iter()
             .for_each(|instruction| self.do_instruction(*instruction));
-    }
-
-    fn do_new_instruction(&mut self, instruction: Instruction) {
-        let (c1, c2) = instruction.coordinates;
-        for i in c1.0..=c2.0 {
-            for j in c1.1..=c2.1 {
-                match instruction.action {
-                    LightAction::TurnOn => self.grid[i][j] += 1,
-                    LightAction::TurnOff => {
-                        self.grid[i][j] = if self.grid[i][j] == 0 {
-                            0
-                        } else {
-                            self.grid[i][j] - 1
-                        }
-                    }
-                    LightAction::Toggle => self.grid[i][j] += 2,
-                }
-            }
-        }
-    }
-
-    fn do_new_instructions(&mut self, instructions: &Vec<Instruction>) {
-        instructions
-            .iter()
-            .for_each(|instruction| self.do_new_instruction(*instruction));
-    }
-
-    fn num_lit_lights(&self) -> usize {
-        self.grid
-            .iter()
-            .map(|inner| inner.iter().filter(|brightness| **brightness == 1).count())
-            .sum()
+        Ok(())
     }
 }
 
@@ -160,6 +389,7 @@ enum LightAction {
     TurnOn,
     TurnOff,
     Toggle,
+    Set(Brightness),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -169,67 +399,484 @@ struct Instruction {
 }
 
 impl Instruction {
+    /// Takes the rectangle's corners in either order.
+    fn new(action: LightAction, c1: Coordinate, c2: Coordinate) -> Self {
+        Self {
+            coordinates: normalise(c1, c2),
+            action,
+        }
+    }
+
+    /// A single rectangle, which must parse.
+    #[cfg(test)]
     fn from(s: &str) -> Self {
-        let mut parts = s.split(' ');
-        let turn_or_toggle = parts.next().unwrap();
-        match turn_or_toggle {
-            "turn" => {
-                let on_or_off = parts.next().unwrap();
-                let c1 = Coordinate::from(parts.next().unwrap());
-                parts.next();
-                let c2 = Coordinate::from(parts.next().unwrap());
-                match on_or_off {
-                    "on" => Self {
-                        coordinates: (c1, c2),
-                        action: LightAction::TurnOn,
-                    },
-                    "off" => Self {
-                        coordinates: (c1, c2),
-                        action: LightAction::TurnOff,
-                    },
-                    s => panic!("unexpected word in instruction: {}", s),
-                }
-            }
-            "toggle" => {
-                let c1 = Coordinate::from(parts.next().unwrap());
-                parts.next();
-                let c2 = Coordinate::from(parts.next().unwrap());
-                Self {
-                    coordinates: (c1, c2),
-                    action: LightAction::Toggle,
-                }
-            }
-            s => panic!("unexpected word in instruction: {}", s),
+        match parse_script(s).unwrap().as_slice() {
+            [statement] => match statement.instructions().as_slice() {
+                [instruction] => *instruction,
+                _ => panic!("not a rectangle: {}", s),
+            },
+            _ => panic!("not one instruction: {}", s),
         }
     }
 }
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.action {
+            LightAction::TurnOn => "turn on",
+            LightAction::TurnOff => "turn off",
+            LightAction::Toggle => "toggle",
+            LightAction::Set(brightness) => {
+                return write!(
+                    f,
+                    "set brightness {} {} through {}",
+                    brightness, self.coordinates.0, self.coordinates.1
+                )
+            }
+        };
+        let (c1, c2) = self.coordinates;
+        write!(f, "{} {} through {}", action, c1, c2)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::script::Shape;
     use super::*;
 
     #[test]
     fn test_do_instruction() {
-        let mut lights = Lights::new();
+        let mut lights = Lights::new(Binary, GRID_DIM, GRID_DIM);
         lights.do_instruction(Instruction {
             coordinates: (Coordinate(0, 0), Coordinate(999, 999)),
             action: LightAction::TurnOn,
         });
-        assert_eq!(lights.num_lit_lights(), 1_000_000);
+        assert_eq!(lights.score(), 1_000_000);
 
-        let mut lights = Lights::new();
+        let mut lights = Lights::new(Binary, GRID_DIM, GRID_DIM);
         lights.do_instruction(Instruction {
             coordinates: (Coordinate(0, 0), Coordinate(999, 0)),
             action: LightAction::Toggle,
         });
-        assert_eq!(lights.num_lit_lights(), 1_000);
+        assert_eq!(lights.score(), 1_000);
 
-        let mut lights = Lights::new();
+        let mut lights = Lights::new(Binary, GRID_DIM, GRID_DIM);
         lights.do_instruction(Instruction {
             coordinates: (Coordinate(499, 499), Coordinate(500, 500)),
             action: LightAction::Toggle,
         });
-        assert_eq!(lights.num_lit_lights(), 4);
+        assert_eq!(lights.score(), 4);
+    }
+
+    #[test]
+    fn test_compressed_lights() {
+        let contents = load("src/day6/input.txt").unwrap();
+        let instructions: Vec<Instruction> = contents.lines().map(Instruction::from).collect();
+        let instructions = &instructions[..50];
+
+        let mut lights = Lights::new(Binary, GRID_DIM, GRID_DIM);
+        lights.do_instructions(instructions).unwrap();
+        let mut compressed = CompressedLights::new(Binary, instructions);
+        compressed.do_instructions(instructions);
+        assert_eq!(compressed.score(), lights.score());
+
+        let mut lights = Lights::new(Dimmer, GRID_DIM, GRID_DIM);
+        lights.do_instructions(instructions).unwrap();
+        let mut compressed = CompressedLights::new(Dimmer, instructions);
+        compressed.do_instructions(instructions);
+        assert_eq!(compressed.score(), lights.score());
+    }
+
+    #[test]
+    fn test_compressed_lights_large_grid() {
+        let instructions = [
+            Instruction::from("turn on 0,0 through 999999,999999"),
+            Instruction::from("toggle 0,0 through 999999,0"),
+            Instruction::from("turn off 499999,499999 through 500000,500000"),
+        ];
+
+        let mut lights = CompressedLights::new(Binary, &instructions);
+        lights.do_instructions(&instructions);
+        assert_eq!(lights.score(), 1_000_000 * 1_000_000 - 1_000_000 - 4);
+
+        let mut lights = CompressedLights::new(Dimmer, &instructions);
+        lights.do_instructions(&instructions);
+        assert_eq!(lights.score(), 1_000_000 * 1_000_000 + 2 * 1_000_000 - 4);
+
+        // no room to split after the last possible light
+        let max = usize::MAX;
+        let statements = parse_script(&format!("turn on 0,0 through {},0", max)).unwrap();
+        assert!(check_statements(&statements, max, max).is_err());
+        let statements = parse_script(&format!("turn on 0,0 through {},0", max - 1)).unwrap();
+        assert_eq!(check_statements(&statements, max, max), Ok(()));
+    }
+
+    #[test]
+    fn test_models() {
+        let instructions = [
+            Instruction::from("turn on 0,0 through 0,0"),
+            Instruction::from("toggle 0,0 through 999,999"),
+        ];
+        for size in [Some((GRID_DIM, GRID_DIM)), None] {
+            assert_eq!(score(Binary, &instructions, size), Ok(1_000_000 - 1));
+            assert_eq!(score(Dimmer, &instructions, size), Ok(1 + 2_000_000));
+            assert_eq!(score(Capped(2), &instructions, size), Ok(2_000_000));
+            assert_eq!(score(Capped(1), &instructions, size), Ok(1_000_000));
+        }
+    }
+
+    #[test]
+    fn test_grid_size() {
+        let instructions = [Instruction::from("turn on 0,0 through 1000,1000")];
+        let mut lights = Lights::new(Binary, GRID_DIM, GRID_DIM);
+        let error = lights.do_instructions(&instructions).unwrap_err();
+        assert_eq!(error.line, 1);
+        assert_eq!(
+            error.to_string(),
+            "line 1: \"turn on 0,0 through 1000,1000\" is outside the 1000x1000 grid"
+        );
+        assert_eq!(lights.score(), 0);
+
+        let mut lights = Lights::new(Binary, 1001, 1001);
+        lights.do_instructions(&instructions).unwrap();
+        assert_eq!(lights.score(), 1001 * 1001);
+
+        // nothing is done if any instruction is out of bounds
+        let instructions = [
+            Instruction::from("toggle 0,0 through 2,1"),
+            Instruction::from("turn on 0,0 through 2,2"),
+        ];
+        let mut lights = Lights::new(Dimmer, 3, 2);
+        let error = lights.do_instructions(&instructions).unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(lights.score(), 0);
+    }
+
+    #[test]
+    fn test_reversed_corners() {
+        assert_eq!(
+            Instruction::from("toggle 500,500 through 499,499"),
+            Instruction::from("toggle 499,499 through 500,500")
+        );
+        assert_eq!(
+            Instruction::from("turn on 5,0 through 0,5").coordinates,
+            (Coordinate(0, 0), Coordinate(5, 5))
+        );
+
+        let mut lights = Lights::new(Binary, GRID_DIM, GRID_DIM);
+        lights
+            .do_instructions(&[Instruction::from("turn on 500,500 through 499,499")])
+            .unwrap();
+        assert_eq!(lights.score(), 4);
+    }
+
+    #[test]
+    fn test_instruction_display() {
+        for line in load("src/day6/input.txt").unwrap().lines() {
+            assert_eq!(Instruction::from(line).to_string(), line);
+        }
+    }
+
+    #[test]
+    fn test_bit_lights() {
+        let contents = load("src/day6/input.txt").unwrap();
+        let instructions: Vec<Instruction> = contents.lines().map(Instruction::from).collect();
+        let instructions = &instructions[..50];
+
+        let mut lights = Lights::new(Binary, GRID_DIM, GRID_DIM);
+        lights.do_instructions(instructions).unwrap();
+        let mut bits = BitLights::new(GRID_DIM, GRID_DIM);
+        bits.do_instructions(instructions).unwrap();
+        assert_eq!(bits.num_lit_lights(), lights.score());
+
+        // rectangles starting and ending on either side of word boundaries
+        let instructions: Vec<Instruction> = [
+            "turn on 0,0 through 2,199",
+            "toggle 1,63 through 3,64",
+            "turn off 0,127 through 3,128",
+            "toggle 2,0 through 3,63",
+            "toggle 0,64 through 0,127",
+            "turn on 3,199 through 3,199",
+        ]
+        .iter()
+        .map(|line| Instruction::from(line))
+        .collect();
+        for n in 0..=instructions.len() {
+            let mut lights = Lights::new(Binary, 4, 200);
+            lights.do_instructions(&instructions[..n]).unwrap();
+            let mut bits = BitLights::new(4, 200);
+            bits.do_instructions(&instructions[..n]).unwrap();
+            assert_eq!(bits.num_lit_lights(), lights.score(), "{}", n);
+        }
+
+        let mut bits = BitLights::new(4, 200);
+        bits.do_instructions(&instructions[..1]).unwrap();
+        let error = bits
+            .do_instructions(&[Instruction::from("toggle 0,0 through 4,0")])
+            .unwrap_err();
+        assert_eq!(error.line, 1);
+        assert_eq!(bits.num_lit_lights(), 600);
+    }
+
+    #[test]
+    fn test_write_image() {
+        let instructions = [
+            Instruction::from("turn on 0,0 through 2,0"),
+            Instruction::from("toggle 2,0 through 2,1"),
+            Instruction::from("turn on 9,1 through 9,1"),
+        ];
+
+        let mut lights = Lights::new(Binary, 10, 2);
+        lights.do_instructions(&instructions).unwrap();
+        let mut image = Vec::new();
+        lights.write_image(Format::Pbm, 1, &mut image).unwrap();
+        let mut expected = b"P4\n10 2\n".to_vec();
+        expected.extend([0b0011_1111, 0b1100_0000, 0b1101_1111, 0b1000_0000]);
+        assert_eq!(image, expected);
+
+        let mut lights = Lights::new(Dimmer, 10, 2);
+        lights.do_instructions(&instructions).unwrap();
+        assert_eq!(lights.max_level(), 3);
+        let mut image = Vec::new();
+        lights.write_image(Format::Pgm, 3, &mut image).unwrap();
+        let mut expected = b"P5\n10 2\n255\n".to_vec();
+        expected.extend([85, 85, 255, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend([0, 0, 170, 0, 0, 0, 0, 0, 0, 85]);
+        assert_eq!(image, expected);
+
+        let mut image = Vec::new();
+        lights.write_image(Format::Ppm, 2, &mut image).unwrap();
+        assert!(image.starts_with(b"P6\n10 2\n255\n"));
+        assert_eq!(image.len(), 12 + 10 * 2 * 3);
+        assert_eq!(&image[12..18], [127, 107, 85, 127, 107, 85]);
+        assert_eq!(&image[18..21], [255, 214, 170]);
+    }
+
+    #[test]
+    fn test_save_frames() {
+        let dir = std::env::temp_dir().join(format!("day6-frames-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let statements = parse_script("toggle 0,0 through 1,1\nturn off 0,0 through 0,0").unwrap();
+        save_frames(Dimmer, (2, 2), &statements, Format::Pgm, &dir, "part2").unwrap();
+
+        let frame = |i| std::fs::read(dir.join(format!("part2-000{}.pgm", i))).unwrap();
+        assert_eq!(&frame(0)[11..], [0, 0, 0, 0]);
+        assert_eq!(&frame(1)[11..], [255, 255, 255, 255]);
+        assert_eq!(&frame(2)[11..], [127, 255, 255, 255]);
+        assert!(!dir.join("part2-0003.pgm").exists());
+
+        let out_of_bounds = parse_script("toggle 0,0 through 2,2").unwrap();
+        assert!(save_frames(Binary, (2, 2), &out_of_bounds, Format::Pbm, &dir, "part1").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn lit(statements: &[Statement], width: usize, height: usize) -> Vec<Coordinate> {
+        let mut lights = Lights::new(Binary, width, height);
+        for statement in statements {
+            lights.do_instructions(&statement.instructions()).unwrap();
+        }
+        let mut lit = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if lights.grid[x][y] {
+                    lit.push(Coordinate(x, y));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let script = "
+            # a comment, then a blank line
+
+            turn on 0,0 through 999,999   # trailing comment
+            region porch 0,990 through 999,999
+            region dot circle 5,5 radius 0
+            set brightness 0 porch
+            toggle  line 0,0 to 3,3
+            set brightness 7 dot
+        ";
+        let statements = parse_script(script).unwrap();
+        let summary: Vec<(usize, LightAction, &str)> = statements
+            .iter()
+            .map(|statement| (statement.line, statement.action, statement.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (4, LightAction::TurnOn, "turn on 0,0 through 999,999"),
+                (7, LightAction::Set(0), "set brightness 0 porch"),
+                (8, LightAction::Toggle, "toggle line 0,0 to 3,3"),
+                (9, LightAction::Set(7), "set brightness 7 dot"),
+            ]
+        );
+        assert_eq!(
+            statements[1].shape,
+            Shape::Rectangle(Coordinate(0, 990), Coordinate(999, 999))
+        );
+
+        let mut lights = Lights::new(Dimmer, GRID_DIM, GRID_DIM);
+        for statement in &statements {
+            lights.do_instructions(&statement.instructions()).unwrap();
+        }
+        assert_eq!(lights.score(), 1_000_000 - 10_000 + 4 * 2 + 6);
+
+        let error = |script| parse_script(script).unwrap_err().to_string();
+        assert_eq!(
+            error("dim 0,0 through 1,1"),
+            "line 1: unknown action \"dim\""
+        );
+        assert_eq!(
+            error("toggle 0,0 to 1,1"),
+            "line 1: expected \"through\", found \"to\""
+        );
+        assert_eq!(
+            error("\nturn on 0,0 through 1,1 now"),
+            "line 2: unexpected \"now\""
+        );
+        assert_eq!(error("toggle porch"), "line 1: unknown region \"porch\"");
+        assert_eq!(
+            error("region a 0,0 through 1,1\nregion a 0,0 through 2,2"),
+            "line 2: region a is already defined"
+        );
+        assert_eq!(
+            error("region circle 0,0 through 1,1"),
+            "line 1: \"circle\" cannot name a region"
+        );
+        assert_eq!(
+            error("turn on circle 2,5 radius 3"),
+            "line 1: circle around 2,5 goes below 0"
+        );
+        let max = usize::MAX;
+        let script = format!("toggle circle {},5 radius 1", max);
+        assert_eq!(
+            error(&script),
+            format!(
+                "line 1: circle around {},5 goes past the largest coordinate",
+                max
+            )
+        );
+        let radius = 1usize << (usize::BITS / 2);
+        let script = format!("toggle circle {0},{0} radius {0}", radius);
+        assert_eq!(
+            error(&script),
+            format!("line 1: radius {} is too large", radius)
+        );
+        assert_eq!(
+            error("set brightness high 0,0 through 1,1"),
+            "line 1: expected a number, found \"high\""
+        );
+        assert_eq!(
+            error("toggle 0;0 through 1,1"),
+            "line 1: expected a coordinate like 3,4, found \"0;0\""
+        );
+        assert_eq!(error("turn"), "line 1: unexpected end of line");
+    }
+
+    #[test]
+    fn test_shapes() {
+        let statements = parse_script("turn on circle 3,3 radius 2").unwrap();
+        let expected: Vec<Coordinate> = [
+            (3, 1),
+            (2, 2),
+            (3, 2),
+            (4, 2),
+            (1, 3),
+            (2, 3),
+            (3, 3),
+            (4, 3),
+            (5, 3),
+            (2, 4),
+            (3, 4),
+            (4, 4),
+            (3, 5),
+        ]
+        .iter()
+        .map(|&(x, y)| Coordinate(x, y))
+        .collect();
+        assert_eq!(lit(&statements, 7, 7), expected);
+
+        let statements = parse_script("toggle line 4,1 to 0,3").unwrap();
+        let expected: Vec<Coordinate> = [(4, 1), (2, 2), (3, 2), (0, 3), (1, 3)]
+            .iter()
+            .map(|&(x, y)| Coordinate(x, y))
+            .collect();
+        assert_eq!(lit(&statements, 5, 4), expected);
+        assert!(statements[0].shape.contains(Coordinate(2, 2)));
+        assert!(!statements[0].shape.contains(Coordinate(2, 1)));
+
+        // a toggled shape changes each light once
+        let statements =
+            parse_script("toggle circle 10,10 radius 7\ntoggle line 0,0 to 20,13").unwrap();
+        for statement in &statements {
+            let cells: usize = statement
+                .instructions()
+                .iter()
+                .map(|instruction| {
+                    let (c1, c2) = instruction.coordinates;
+                    (c2.0 - c1.0 + 1) * (c2.1 - c1.1 + 1)
+                })
+                .sum();
+            assert_eq!(cells, lit(std::slice::from_ref(statement), 21, 21).len());
+        }
+
+        let statements =
+            parse_script("turn on line 0,0 to 0,0\ntoggle circle 5,5 radius 5").unwrap();
+        assert_eq!(check_statements(&statements, 11, 11), Ok(()));
+        let error = check_statements(&statements, 10, 11).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 2: \"toggle circle 5,5 radius 5\" is outside the 10x11 grid"
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let script: String = (0..100)
+            .map(|i| format!("toggle {},0 through {},{}\n", i % 7, i % 7 + i % 5, i % 3))
+            .collect();
+        let statements = parse_script(&script).unwrap();
+        let mut history = History::new(Lights::new(Binary, 20, 5));
+        let mut expected = vec![0];
+        for (n, statement) in statements.iter().enumerate() {
+            history.push(statement.clone()).unwrap();
+            expected.push(lit(&statements[..n + 1], 20, 5).len());
+        }
+
+        for &n in &[100, 0, 64, 63, 31, 32, 33, 99, 1, 100, 50] {
+            history.seek(n);
+            assert_eq!(history.lights().score(), expected[n], "{}", n);
+        }
+
+        history.seek(100);
+        let last = history.last_affecting(Coordinate(10, 1)).unwrap();
+        assert_eq!(
+            (last.line, last.text.as_str()),
+            (35, "toggle 6,0 through 10,1")
+        );
+        let last = history.last_affecting(Coordinate(0, 0)).unwrap();
+        assert_eq!(last.line, 99);
+        assert_eq!(history.last_affecting(Coordinate(19, 4)), None);
+        history.seek(3);
+        let last = history.last_affecting(Coordinate(0, 0)).unwrap();
+        assert_eq!(last.line, 1);
+
+        // pushing after a rewind forgets what came after
+        history.seek(40);
+        let extra = parse_script("turn on 0,0 through 19,4").unwrap();
+        history.push(extra[0].clone()).unwrap();
+        assert_eq!(history.len(), 41);
+        assert_eq!(history.lights().score(), 100);
+        history.seek(40);
+        assert_eq!(history.lights().score(), expected[40]);
+        history.seek(41);
+        assert_eq!(history.lights().score(), 100);
+
+        let too_big = parse_script("turn on 0,0 through 20,4").unwrap();
+        assert!(history.push(too_big[0].clone()).is_err());
+        assert_eq!(history.len(), 41);
     }
 
     #[test]
@@ -0,0 +1,118 @@
+/*!
+What the instructions mean for a single light.
+
+A grid only walks rectangles; the model decides how each light responds to
+`turn on`, `turn off` and `toggle`, and how much it adds to the final score.
+*/
+
+use super::{Brightness, LightAction};
+
+pub trait LightModel {
+    type Cell: Copy + Default;
+
+    fn turn_on(&self, cell: &mut Self::Cell);
+
+    fn turn_off(&self, cell: &mut Self::Cell);
+
+    fn toggle(&self, cell: &mut Self::Cell);
+
+    /// `set brightness`, where any brightness above 0 counts as on.
+    fn set(&self, cell: &mut Self::Cell, brightness: Brightness);
+
+    /// How much one light adds to the grid's score.
+    fn score(&self, cell: Self::Cell) -> usize;
+
+    fn apply(&self, action: LightAction, cell: &mut Self::Cell) {
+        match action {
+            LightAction::TurnOn => self.turn_on(cell),
+            LightAction::TurnOff => self.turn_off(cell),
+            LightAction::Toggle => self.toggle(cell),
+            LightAction::Set(brightness) => self.set(cell, brightness),
+        }
+    }
+}
+
+/// Part 1: lights are either on or off, and the score is how many are on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Binary;
+
+impl LightModel for Binary {
+    type Cell = bool;
+
+    fn turn_on(&self, cell: &mut bool) {
+        *cell = true;
+    }
+
+    fn turn_off(&self, cell: &mut bool) {
+        *cell = false;
+    }
+
+    fn toggle(&self, cell: &mut bool) {
+        *cell = !*cell;
+    }
+
+    fn set(&self, cell: &mut bool, brightness: Brightness) {
+        *cell = brightness > 0;
+    }
+
+    fn score(&self, cell: bool) -> usize {
+        cell as usize
+    }
+}
+
+/// Part 2: `turn on` adds 1, `turn off` takes 1 away down to 0, `toggle` adds
+/// 2, and the score is the total brightness.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Dimmer;
+
+impl LightModel for Dimmer {
+    type Cell = Brightness;
+
+    fn turn_on(&self, cell: &mut Brightness) {
+        *cell += 1;
+    }
+
+    fn turn_off(&self, cell: &mut Brightness) {
+        *cell = cell.saturating_sub(1);
+    }
+
+    fn toggle(&self, cell: &mut Brightness) {
+        *cell += 2;
+    }
+
+    fn set(&self, cell: &mut Brightness, brightness: Brightness) {
+        *cell = brightness;
+    }
+
+    fn score(&self, cell: Brightness) -> usize {
+        cell
+    }
+}
+
+/// A dimmer that cannot go brighter than its maximum.
+#[derive(Clone, Copy, Debug)]
+pub struct Capped(pub Brightness);
+
+impl LightModel for Capped {
+    type Cell = Brightness;
+
+    fn turn_on(&self, cell: &mut Brightness) {
+        *cell = (*cell + 1).min(self.0);
+    }
+
+    fn turn_off(&self, cell: &mut Brightness) {
+        *cell = cell.saturating_sub(1);
+    }
+
+    fn toggle(&self, cell: &mut Brightness) {
+        *cell = (*cell + 2).min(self.0);
+    }
+
+    fn set(&self, cell: &mut Brightness, brightness: Brightness) {
+        *cell = brightness.min(self.0);
+    }
+
+    fn score(&self, cell: Brightness) -> usize {
+        cell
+    }
+}
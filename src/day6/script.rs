@@ -0,0 +1,322 @@
+/*!
+Santa's instructions, extended.
+
+Each line of a script is one statement, and `#` starts a comment:
+
+    # the original instructions still work
+    turn on 0,0 through 999,999
+    toggle 0,0 through 999,0
+
+    # circles and lines
+    turn off circle 500,500 radius 20
+    toggle line 0,0 to 999,999
+
+    # absolute brightness
+    set brightness 5 10,10 through 20,20
+
+    # named regions, defined before use
+    region porch 0,990 through 999,999
+    set brightness 0 porch
+
+Every shape is drawn as rectangles that do not overlap, so a statement changes
+each light it covers exactly once and every grid can follow it.
+*/
+
+use super::{
+    normalise, Brightness, Coordinate, CoordinatePair, Instruction, LightAction, OutOfBounds,
+};
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// Inclusive corners, smallest first.
+    Rectangle(Coordinate, Coordinate),
+    /// Lights no further than `radius` from the centre.
+    Circle { center: Coordinate, radius: usize },
+    /// The lights a straight line between two points passes through.
+    Line(Coordinate, Coordinate),
+}
+
+impl Shape {
+    /// The smallest rectangle covering the shape.
+    pub fn bounds(&self) -> CoordinatePair {
+        match *self {
+            Shape::Rectangle(c1, c2) => (c1, c2),
+            Shape::Circle { center, radius } => (
+                Coordinate(center.0 - radius, center.1 - radius),
+                Coordinate(center.0 + radius, center.1 + radius),
+            ),
+            Shape::Line(a, b) => (
+                Coordinate(a.0.min(b.0), a.1.min(b.1)),
+                Coordinate(a.0.max(b.0), a.1.max(b.1)),
+            ),
+        }
+    }
+
+    /// Disjoint rectangles that together cover exactly the shape.
+    pub fn rectangles(&self) -> Vec<CoordinatePair> {
+        match *self {
+            Shape::Rectangle(c1, c2) => vec![(c1, c2)],
+            Shape::Circle { center, radius } => (center.0 - radius..=center.0 + radius)
+                .map(|x| {
+                    let dx = x.abs_diff(center.0);
+                    let half = (radius * radius - dx * dx).isqrt();
+                    (
+                        Coordinate(x, center.1 - half),
+                        Coordinate(x, center.1 + half),
+                    )
+                })
+                .collect(),
+            Shape::Line(a, b) => line(a, b).into_iter().map(|c| (c, c)).collect(),
+        }
+    }
+
+    pub fn contains(&self, c: Coordinate) -> bool {
+        self.rectangles()
+            .iter()
+            .any(|(c1, c2)| (c1.0..=c2.0).contains(&c.0) && (c1.1..=c2.1).contains(&c.1))
+    }
+}
+
+/// Bresenham's line, one light per step along the longer axis.
+fn line(a: Coordinate, b: Coordinate) -> Vec<Coordinate> {
+    let (x1, y1) = (b.0 as i64, b.1 as i64);
+    let (mut x, mut y) = (a.0 as i64, a.1 as i64);
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut error = dx + dy;
+    let mut lights = vec![];
+    loop {
+        lights.push(Coordinate(x as usize, y as usize));
+        if (x, y) == (x1, y1) {
+            return lights;
+        }
+        let twice = 2 * error;
+        if twice >= dy {
+            error += dy;
+            x += sx;
+        }
+        if twice <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement {
+    /// Counting from 1 in the script.
+    pub line: usize,
+    pub action: LightAction,
+    pub shape: Shape,
+    /// The statement as written, without its comment.
+    pub text: String,
+}
+
+impl Statement {
+    pub fn instructions(&self) -> Vec<Instruction> {
+        self.shape
+            .rectangles()
+            .into_iter()
+            .map(|(c1, c2)| Instruction::new(self.action, c1, c2))
+            .collect()
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Finds the first statement that reaches past the edge of a grid.
+pub fn check_statements(
+    statements: &[Statement],
+    width: usize,
+    height: usize,
+) -> Result<(), OutOfBounds> {
+    let outside = statements.iter().find(|statement| {
+        let (_, c2) = statement.shape.bounds();
+        c2.0 >= width || c2.1 >= height
+    });
+    match outside {
+        Some(statement) => Err(OutOfBounds {
+            line: statement.line,
+            text: statement.text.clone(),
+            width,
+            height,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+pub fn parse_script(text: &str) -> Result<Vec<Statement>, ParseError> {
+    let mut regions = HashMap::new();
+    let mut statements = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let tokens: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+        if tokens.is_empty() {
+            continue;
+        }
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            regions: &regions,
+        };
+        let error = |message| ParseError {
+            line: i + 1,
+            message,
+        };
+
+        if parser.eat("region") {
+            let name = parser.name().map_err(error)?.to_string();
+            let shape = parser.shape().and_then(|shape| parser.end(shape));
+            let shape = shape.map_err(error)?;
+            if regions.contains_key(&name) {
+                return Err(error(format!("region {} is already defined", name)));
+            }
+            regions.insert(name, shape);
+            continue;
+        }
+
+        let action = parser.action().map_err(error)?;
+        let shape = parser.shape().and_then(|shape| parser.end(shape));
+        statements.push(Statement {
+            line: i + 1,
+            action,
+            shape: shape.map_err(error)?,
+            text: tokens.join(" "),
+        });
+    }
+    Ok(statements)
+}
+
+struct Parser<'a> {
+    tokens: &'a [&'a str],
+    pos: usize,
+    regions: &'a HashMap<String, Shape>,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self.tokens.get(self.pos).ok_or("unexpected end of line")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.tokens.get(self.pos) == Some(&word);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == word => Ok(()),
+            token => Err(format!("expected {:?}, found {:?}", word, token)),
+        }
+    }
+
+    fn end<T>(&self, value: T) -> Result<T, String> {
+        match self.tokens.get(self.pos) {
+            None => Ok(value),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        let token = self.next()?;
+        token
+            .parse()
+            .map_err(|_| format!("expected a number, found {:?}", token))
+    }
+
+    fn coordinate(&mut self) -> Result<Coordinate, String> {
+        let token = self.next()?;
+        token.parse()
+    }
+
+    fn name(&mut self) -> Result<&'a str, String> {
+        let token = self.next()?;
+        let valid = token.starts_with(|c: char| c.is_ascii_alphabetic())
+            && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !["circle", "line"].contains(&token);
+        if valid {
+            Ok(token)
+        } else {
+            Err(format!("{:?} cannot name a region", token))
+        }
+    }
+
+    fn action(&mut self) -> Result<LightAction, String> {
+        match self.next()? {
+            "turn" => match self.next()? {
+                "on" => Ok(LightAction::TurnOn),
+                "off" => Ok(LightAction::TurnOff),
+                token => Err(format!("expected \"on\" or \"off\", found {:?}", token)),
+            },
+            "toggle" => Ok(LightAction::Toggle),
+            "set" => {
+                self.expect("brightness")?;
+                Ok(LightAction::Set(self.number()? as Brightness))
+            }
+            token => Err(format!("unknown action {:?}", token)),
+        }
+    }
+
+    fn shape(&mut self) -> Result<Shape, String> {
+        if self.eat("circle") {
+            let center = self.coordinate()?;
+            self.expect("radius")?;
+            let radius = self.number()?;
+            if center.0 < radius || center.1 < radius {
+                return Err(format!("circle around {} goes below 0", center));
+            }
+            let fits = |c: usize| c.checked_add(radius).is_some();
+            if !fits(center.0) || !fits(center.1) {
+                return Err(format!(
+                    "circle around {} goes past the largest coordinate",
+                    center
+                ));
+            }
+            if radius.checked_mul(radius).is_none() {
+                return Err(format!("radius {} is too large", radius));
+            }
+            return Ok(Shape::Circle { center, radius });
+        }
+        if self.eat("line") {
+            let a = self.coordinate()?;
+            self.expect("to")?;
+            return Ok(Shape::Line(a, self.coordinate()?));
+        }
+
+        let token = self.next()?;
+        if let Some(shape) = self.regions.get(token) {
+            return Ok(shape.clone());
+        }
+        if !token.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(format!("unknown region {:?}", token));
+        }
+        let c1: Coordinate = token.parse()?;
+        self.expect("through")?;
+        let c2 = self.coordinate()?;
+        let (c1, c2) = normalise(c1, c2);
+        Ok(Shape::Rectangle(c1, c2))
+    }
+}
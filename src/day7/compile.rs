@@ -0,0 +1,219 @@
+/*!
+The booklet compiled to a straight-line program.
+
+Each wire gets a slot in a `Vec` of words, numbered in dependency order, and each
+gate becomes one op reading and writing slots by index, so running the
+circuit is a single pass with no name lookups. Literal wires start at their
+value, and literal operands get read-only slots after the wires.
+*/
+
+use super::{dependency_order, CircuitError, Instruction, Operand, Word};
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+pub type Slot = u32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    And,
+    Or,
+    Xor,
+    Nand,
+    Nor,
+    Not,
+    Rshift,
+    Lshift,
+    Rrotate,
+    Lrotate,
+    /// Bits of `b` where `a` is clear and of `c` where it is set.
+    Mux,
+    Copy,
+}
+
+/// Writes `a <code> b` to `out`, where `b` is the amount for shifts and
+/// rotates and unused for `Not` and `Copy`, and only `Mux` reads `c`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Op {
+    pub code: Code,
+    pub a: Slot,
+    pub b: Slot,
+    pub c: Slot,
+    pub out: Slot,
+}
+
+pub struct Program<'a, W: Word = u16> {
+    /// Wire names by slot.
+    names: Vec<&'a str>,
+    slots: HashMap<&'a str, Slot>,
+    /// Every slot's signal before the first op.
+    initial: Vec<W>,
+    ops: Vec<Op>,
+}
+
+impl<'a, W: Word> Program<'a, W> {
+    pub fn compile(booklet: &'a [Instruction<'a, W>]) -> Result<Self, CircuitError<'a>> {
+        let order = dependency_order(booklet)?;
+        let names: Vec<&str> = order
+            .iter()
+            .map(|instruction| instruction.output())
+            .collect();
+        let slots: HashMap<&str, Slot> = names
+            .iter()
+            .enumerate()
+            .map(|(slot, name)| (*name, slot as Slot))
+            .collect();
+        let mut initial = vec![W::default(); names.len()];
+        let mut constants = HashMap::new();
+        let mut slot = |operand: Operand<W>| match operand {
+            Operand::Wire(wire) => slots[wire],
+            Operand::Literal(value) => *constants.entry(value).or_insert_with(|| {
+                initial.push(value);
+                (initial.len() - 1) as Slot
+            }),
+        };
+
+        let mut ops = Vec::with_capacity(order.len());
+        let mut literals = vec![];
+        for (out, instruction) in order.iter().enumerate() {
+            let out = out as Slot;
+            let (code, a, b, c) = match **instruction {
+                Instruction::Literal(value, _) => {
+                    literals.push((out, value));
+                    continue;
+                }
+                Instruction::And(in1, in2, _) => (Code::And, slot(in1), slot(in2), 0),
+                Instruction::Or(in1, in2, _) => (Code::Or, slot(in1), slot(in2), 0),
+                Instruction::Xor(in1, in2, _) => (Code::Xor, slot(in1), slot(in2), 0),
+                Instruction::Nand(in1, in2, _) => (Code::Nand, slot(in1), slot(in2), 0),
+                Instruction::Nor(in1, in2, _) => (Code::Nor, slot(in1), slot(in2), 0),
+                Instruction::Not(input, _) => (Code::Not, slot(input), 0, 0),
+                Instruction::Rshift(input, amount, _) => {
+                    (Code::Rshift, slot(input), amount.into(), 0)
+                }
+                Instruction::Lshift(input, amount, _) => {
+                    (Code::Lshift, slot(input), amount.into(), 0)
+                }
+                Instruction::Rrotate(input, amount, _) => {
+                    (Code::Rrotate, slot(input), amount.into(), 0)
+                }
+                Instruction::Lrotate(input, amount, _) => {
+                    (Code::Lrotate, slot(input), amount.into(), 0)
+                }
+                Instruction::Mux(select, in1, in2, _) => {
+                    (Code::Mux, slot(select), slot(in1), slot(in2))
+                }
+                Instruction::Move(input, _) => (Code::Copy, slot(Operand::Wire(input)), 0, 0),
+            };
+            ops.push(Op { code, a, b, c, out });
+        }
+        for (slot, value) in literals {
+            initial[slot as usize] = value;
+        }
+
+        Ok(Self {
+            names,
+            slots,
+            initial,
+            ops,
+        })
+    }
+
+    pub fn slot(&self, wire: &str) -> Option<Slot> {
+        self.slots.get(wire).copied()
+    }
+
+    /// Every slot's signal, with some wires held at a signal whatever their
+    /// source says.
+    pub fn run(&self, pins: &[(Slot, W)]) -> Vec<W> {
+        let mut signals = self.initial.clone();
+        let mut pinned = vec![false; self.names.len()];
+        for &(slot, value) in pins {
+            signals[slot as usize] = value;
+            pinned[slot as usize] = true;
+        }
+        for op in &self.ops {
+            if pinned[op.out as usize] {
+                continue;
+            }
+            let a = signals[op.a as usize];
+            let b = || signals[op.b as usize];
+            signals[op.out as usize] = match op.code {
+                Code::And => a & b(),
+                Code::Or => a | b(),
+                Code::Xor => a ^ b(),
+                Code::Nand => !(a & b()),
+                Code::Nor => !(a | b()),
+                Code::Not => !a,
+                Code::Rshift => a.shr(op.b),
+                Code::Lshift => a.shl(op.b),
+                Code::Rrotate => a.rotate_right(op.b),
+                Code::Lrotate => a.rotate_left(op.b),
+                Code::Mux => a.select(b(), signals[op.c as usize]),
+                Code::Copy => a,
+            };
+        }
+        signals
+    }
+
+    /// Rust source for a function `name` returning every wire's signal in slot
+    /// order, with a `WIRES` array naming them. Wires become `w_` locals, so
+    /// names like `if` and `in` stay valid, and literals carry their type.
+    pub fn to_rust(&self, name: &str) -> String {
+        let wires = self.names.len();
+        let operand = |slot: Slot| match self.names.get(slot as usize) {
+            Some(wire) => format!("w_{}", wire),
+            None => format!("{}{}", self.initial[slot as usize], W::NAME),
+        };
+
+        let mut source = String::new();
+        let names: Vec<String> = self
+            .names
+            .iter()
+            .map(|name| format!("{:?}", name))
+            .collect();
+        writeln!(
+            source,
+            "pub const WIRES: [&str; {}] = [{}];",
+            wires,
+            names.join(", ")
+        )
+        .unwrap();
+        writeln!(source).unwrap();
+        writeln!(source, "pub fn {}() -> [{}; {}] {{", name, W::NAME, wires).unwrap();
+        let mut ops = self.ops.iter().peekable();
+        for (slot, wire) in self.names.iter().enumerate() {
+            let expression = match ops.next_if(|op| op.out as usize == slot) {
+                None => format!("{}", self.initial[slot]),
+                Some(op) => {
+                    let (a, b) = (operand(op.a), || operand(op.b));
+                    match op.code {
+                        Code::And => format!("{} & {}", a, b()),
+                        Code::Or => format!("{} | {}", a, b()),
+                        Code::Xor => format!("{} ^ {}", a, b()),
+                        Code::Nand => format!("!({} & {})", a, b()),
+                        Code::Nor => format!("!({} | {})", a, b()),
+                        Code::Not => format!("!{}", a),
+                        // Rust's shifts overflow rather than clearing every bit
+                        Code::Rshift | Code::Lshift if op.b >= W::BITS => "0".to_string(),
+                        Code::Rshift => format!("{} >> {}", a, op.b),
+                        Code::Lshift => format!("{} << {}", a, op.b),
+                        Code::Rrotate => format!("{}.rotate_right({})", a, op.b),
+                        Code::Lrotate => format!("{}.rotate_left({})", a, op.b),
+                        Code::Mux => format!("({0} & {2}) | (!{0} & {1})", a, b(), operand(op.c)),
+                        Code::Copy => a,
+                    }
+                }
+            };
+            writeln!(source, "    let w_{}: {} = {};", wire, W::NAME, expression).unwrap();
+        }
+        let locals: Vec<String> = self
+            .names
+            .iter()
+            .map(|name| format!("w_{}", name))
+            .collect();
+        writeln!(source, "    [{}]", locals.join(", ")).unwrap();
+        writeln!(source, "}}").unwrap();
+        source
+    }
+}
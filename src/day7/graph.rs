@@ -0,0 +1,165 @@
+/*!
+The booklet as a circuit diagram.
+
+Every instruction becomes a node shaped after its gate, and every wire becomes
+an edge from the gate giving it a signal to each gate reading it. Wires that
+nothing reads end at a node carrying their name, so outputs such as `a` stand
+out. Literal inputs are written into the gate's label rather than drawn.
+*/
+
+use super::{dependency_order, CircuitError, Instruction, Operand, Word};
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Writes the booklet in Graphviz's DOT language, labelling each wire with
+/// its signal when `signals` are given.
+pub fn write_dot<W: Word, O: Write>(
+    booklet: &[Instruction<W>],
+    signals: Option<&HashMap<&str, W>>,
+    mut out: O,
+) -> io::Result<()> {
+    let sources: HashMap<&str, usize> = booklet
+        .iter()
+        .enumerate()
+        .map(|(i, instruction)| (instruction.output(), i))
+        .collect();
+    let readers = readers(booklet);
+    let label = |wire: &str| match signals.and_then(|signals| signals.get(wire)) {
+        Some(signal) => format!("{} = {}", wire, signal),
+        None => wire.to_string(),
+    };
+
+    writeln!(out, "digraph booklet {{")?;
+    writeln!(out, "    rankdir=LR;")?;
+    for (i, instruction) in booklet.iter().enumerate() {
+        let (shape, gate) = gate(instruction);
+        writeln!(out, "    g{} [shape={}, label={:?}];", i, shape, gate)?;
+    }
+    for (i, instruction) in booklet.iter().enumerate() {
+        for input in instruction.inputs() {
+            let from = match sources.get(input) {
+                Some(source) => format!("g{}", source),
+                None => {
+                    // read but never given a signal
+                    writeln!(out, "    {:?} [shape=plaintext, fontcolor=red];", input)?;
+                    format!("{:?}", input)
+                }
+            };
+            writeln!(out, "    {} -> g{} [label={:?}];", from, i, label(input))?;
+        }
+        let output = instruction.output();
+        if !readers.contains_key(output) {
+            writeln!(out, "    {:?} [shape=plaintext];", output)?;
+            writeln!(
+                out,
+                "    g{} -> {:?} [label={:?}];",
+                i,
+                output,
+                label(output)
+            )?;
+        }
+    }
+    writeln!(out, "}}")?;
+    out.flush()
+}
+
+/// The node shape and label for an instruction's gate.
+fn gate<W: Word>(instruction: &Instruction<W>) -> (&'static str, String) {
+    let literal = |operand: &Operand<W>| match operand {
+        Operand::Wire(_) => None,
+        Operand::Literal(value) => Some(value.to_string()),
+    };
+    // literal inputs go where they would be written, wires are left out
+    let infix = |in1: &Operand<W>, op: String, in2: Option<&Operand<W>>| {
+        let parts = vec![literal(in1), Some(op), in2.and_then(literal)];
+        parts.into_iter().flatten().collect::<Vec<_>>().join(" ")
+    };
+    let prefix = |op: &str, inputs: &[&Operand<W>]| {
+        if inputs.iter().all(|input| literal(input).is_none()) {
+            return op.to_string();
+        }
+        let inputs = inputs
+            .iter()
+            .map(|input| literal(input).unwrap_or_else(|| "_".to_string()));
+        std::iter::once(op.to_string())
+            .chain(inputs)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let op = |op: &str| op.to_string();
+    match instruction {
+        Instruction::Literal(value, _) => ("box", value.to_string()),
+        Instruction::And(in1, in2, _) => ("invhouse", infix(in1, op("AND"), Some(in2))),
+        Instruction::Nand(in1, in2, _) => ("invhouse", infix(in1, op("NAND"), Some(in2))),
+        Instruction::Or(in1, in2, _) => ("invtrapezium", infix(in1, op("OR"), Some(in2))),
+        Instruction::Nor(in1, in2, _) => ("invtrapezium", infix(in1, op("NOR"), Some(in2))),
+        Instruction::Xor(in1, in2, _) => ("invtrapezium", infix(in1, op("XOR"), Some(in2))),
+        Instruction::Not(input, _) => ("invtriangle", prefix("NOT", &[input])),
+        Instruction::Rshift(input, amount, _) => {
+            ("cds", infix(input, format!(">> {}", amount), None))
+        }
+        Instruction::Lshift(input, amount, _) => {
+            ("cds", infix(input, format!("<< {}", amount), None))
+        }
+        Instruction::Rrotate(input, amount, _) => {
+            ("cds", infix(input, format!(">>> {}", amount), None))
+        }
+        Instruction::Lrotate(input, amount, _) => {
+            ("cds", infix(input, format!("<<< {}", amount), None))
+        }
+        Instruction::Mux(select, in1, in2, _) => ("trapezium", prefix("MUX", &[select, in1, in2])),
+        Instruction::Move(..) => ("circle", String::new()),
+    }
+}
+
+/// The wires reading each wire, in booklet order.
+fn readers<'a, W: Word>(booklet: &[Instruction<'a, W>]) -> HashMap<&'a str, Vec<&'a str>> {
+    let mut readers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for instruction in booklet {
+        for input in instruction.inputs() {
+            readers.entry(input).or_default().push(instruction.output());
+        }
+    }
+    readers
+}
+
+/// How one wire is connected.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Net<'a> {
+    pub wire: &'a str,
+    /// The wires its source reads.
+    pub fan_in: Vec<&'a str>,
+    /// The wires whose sources read it.
+    pub fan_out: Vec<&'a str>,
+    /// The most gates between it and a literal, with literals at 0.
+    pub depth: usize,
+}
+
+/// Every wire's connections, in dependency order.
+pub fn netlist<'a, W: Word>(
+    booklet: &'a [Instruction<'a, W>],
+) -> Result<Vec<Net<'a>>, CircuitError<'a>> {
+    let mut readers = readers(booklet);
+    let mut depths = HashMap::new();
+    let nets = dependency_order(booklet)?
+        .into_iter()
+        .map(|instruction| {
+            let wire = instruction.output();
+            let fan_in = instruction.inputs();
+            let depth = fan_in
+                .iter()
+                .map(|input| depths[input] + 1)
+                .max()
+                .unwrap_or(0);
+            depths.insert(wire, depth);
+            Net {
+                wire,
+                fan_in,
+                fan_out: readers.remove(wire).unwrap_or_default(),
+                depth,
+            }
+        })
+        .collect();
+    Ok(nets)
+}
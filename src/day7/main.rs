@@ -41,177 +41,829 @@ y: 456
 In little Bobby's kit's instructions booklet (provided as your puzzle input), what signal is ultimately provided to wire a?
 */
 
-use util::load;
+mod compile;
+mod graph;
+mod optimise;
+mod symbolic;
+mod word;
 
-use std::io;
+use compile::Program;
+use graph::{netlist, write_dot};
+use optimise::optimise;
+use symbolic::Symbolic;
+use word::Word;
+
+use util::{flag_value, flag_values, invalid_data, load, parse_flag, positional};
+
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::time::{Duration, Instant};
 
 use std::collections::{HashMap, HashSet};
 
 fn main() -> io::Result<()> {
-    let contents = load("src/day7/input.txt")?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = positional(&args, &VALUE_FLAGS)
+        .first()
+        .copied()
+        .unwrap_or("src/day7/input.txt");
+    let bits = match flag_value(&args, "--bits") {
+        Some(bits) => parse_flag("--bits", bits)?,
+        None => 16,
+    };
+    let contents = load(path)?;
+
+    match bits {
+        8 => solve::<u8>(&args, &contents),
+        16 => solve::<u16>(&args, &contents),
+        32 => solve::<u32>(&args, &contents),
+        64 => solve::<u64>(&args, &contents),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("bad --bits {}, expected 8, 16, 32 or 64", bits),
+        )),
+    }
+}
+
+/// Both parts and every requested extra, with wires `W` wide.
+fn solve<W: Word>(args: &[String], contents: &str) -> io::Result<()> {
+    let settings = flag_values(args, "--set")
+        .map(parse_setting::<W>)
+        .collect::<io::Result<Vec<_>>>()?;
+    let booklet = parse_booklet::<W>(contents).map_err(invalid_data)?;
+
+    if args.iter().any(|arg| arg == "--bench") {
+        return bench(&booklet);
+    }
+    let inputs: Vec<&str> = flag_values(args, "--input").collect();
+    if let Some(output) = flag_value(args, "--optimise") {
+        return print_optimised(&booklet, output, &inputs);
+    }
+    if let Some(wire) = flag_value(args, "--explain") {
+        return explain(&booklet, wire, &inputs);
+    }
+
+    let mut wires = Wires::new();
+    wires.run_booklet(&booklet).map_err(invalid_data)?;
+
+    let a = signal_on(&wires, "a")?;
+    println!("part1: {}", a);
+
+    wires.pin("b", a).ok_or_else(|| invalid_data("no wire b"))?;
+    println!("part2: {}", signal_on(&wires, "a")?);
+
+    if !settings.is_empty() {
+        what_if(&booklet, &settings)?;
+    }
+
+    if let Some(path) = flag_value(args, "--dot") {
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet).map_err(invalid_data)?;
+        let signals = args
+            .iter()
+            .any(|arg| arg == "--values")
+            .then_some(&wires.inner);
+        write_dot(&booklet, signals, BufWriter::new(File::create(path)?))?;
+    }
+
+    if let Some(path) = flag_value(args, "--rust") {
+        let program = Program::compile(&booklet).map_err(invalid_data)?;
+        std::fs::write(path, program.to_rust("circuit"))?;
+    }
 
-    let booklet = read_booklet(&contents);
+    if args.iter().any(|arg| arg == "--netlist") {
+        for net in netlist(&booklet).map_err(invalid_data)? {
+            let names = |wires: &[&str]| match wires {
+                [] => "-".to_string(),
+                wires => wires.join(","),
+            };
+            println!(
+                "{:<4} depth {:<3} in {:<8} out {}",
+                net.wire,
+                net.depth,
+                names(&net.fan_in),
+                names(&net.fan_out)
+            );
+        }
+    }
 
+    Ok(())
+}
+
+/// Pins wires on a fresh circuit and prints every wire whose signal changes.
+fn what_if<W: Word>(booklet: &[Instruction<W>], settings: &[(&str, W)]) -> io::Result<()> {
     let mut wires = Wires::new();
-    wires.run_booklet(&booklet);
+    wires.run_booklet(booklet).map_err(invalid_data)?;
+
+    let mut changes: Vec<Change<W>> = Vec::new();
+    for &(wire, value) in settings {
+        let pinned = wires.pin(wire, value).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("no wire {}", wire))
+        })?;
+        for change in pinned {
+            match changes
+                .iter_mut()
+                .find(|earlier| earlier.wire == change.wire)
+            {
+                Some(earlier) => earlier.after = change.after,
+                None => changes.push(change),
+            }
+        }
+    }
+    changes.retain(|change| change.before != change.after);
+
+    for change in &changes {
+        println!("{}: {} -> {}", change.wire, change.before, change.after);
+    }
+    println!("{} wires changed", changes.len());
+    Ok(())
+}
+
+fn check_wires<W: Word>(booklet: &[Instruction<W>], wires: &[&str]) -> io::Result<()> {
+    for wire in wires {
+        if !booklet
+            .iter()
+            .any(|instruction| instruction.output() == *wire)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no wire {}", wire),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Prints the booklet reduced to what `output` needs, then how much went.
+fn print_optimised<W: Word>(
+    booklet: &[Instruction<W>],
+    output: &str,
+    inputs: &[&str],
+) -> io::Result<()> {
+    check_wires(booklet, &[output])?;
+    check_wires(booklet, inputs)?;
+    let (reduced, stats) = optimise(booklet, output, inputs).map_err(invalid_data)?;
+    for instruction in &reduced {
+        println!("{}", instruction);
+    }
+    eprintln!("{}", stats);
+    Ok(())
+}
+
+/// Prints what a wire is built from and its signal in terms of the inputs,
+/// then which wires each input can change.
+fn explain<W: Word>(booklet: &[Instruction<W>], wire: &str, inputs: &[&str]) -> io::Result<()> {
+    check_wires(booklet, &[wire])?;
+    check_wires(booklet, inputs)?;
+    let symbolic = Symbolic::new(booklet, inputs).map_err(invalid_data)?;
+
+    let sources: Vec<&str> = symbolic.sources(wire).unwrap().iter().copied().collect();
+    println!("{} is built from {}", wire, sources.join(", "));
+    let inputs_read: Vec<&str> = symbolic
+        .expression(wire)
+        .unwrap()
+        .inputs()
+        .into_iter()
+        .collect();
+    if !inputs.is_empty() {
+        println!("{} still reads {}", wire, inputs_read.join(", "));
+    }
+    for (name, expression) in symbolic.definitions(wire).unwrap() {
+        println!("{} = {}", name, expression);
+    }
+    for input in inputs {
+        let affected = symbolic.affected_by(input);
+        println!(
+            "{} can change {} wires: {}",
+            input,
+            affected.len(),
+            affected.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Times both parts through the interpreter and through the compiled program.
+fn bench<W: Word>(booklet: &[Instruction<W>]) -> io::Result<()> {
+    let time = |name: &str, run: &dyn Fn() -> (W, W)| {
+        let mut best = Duration::MAX;
+        let mut answers = (W::default(), W::default());
+        for _ in 0..5 {
+            let start = Instant::now();
+            for _ in 0..100 {
+                answers = run();
+            }
+            best = best.min(start.elapsed() / 100);
+        }
+        println!("{:>11}: {:?} in {:?}", name, answers, best);
+    };
+
+    Wires::new().run_booklet(booklet).map_err(invalid_data)?;
+    time("interpreted", &|| {
+        let mut wires = Wires::new();
+        wires.run_booklet(booklet).unwrap();
+        let a = wires.inner["a"];
+        wires.pin("b", a).unwrap();
+        (a, wires.inner["a"])
+    });
 
-    println!("part1: {}", wires.inner.get("a").unwrap());
+    let program = Program::compile(booklet).map_err(invalid_data)?;
+    let (a, b) = match (program.slot("a"), program.slot("b")) {
+        (Some(a), Some(b)) => (a as usize, b),
+        _ => return Err(invalid_data("no wire a or b")),
+    };
+    time("compiled", &|| {
+        let part1 = program.run(&[])[a];
+        (part1, program.run(&[(b, part1)])[a])
+    });
 
     Ok(())
 }
 
-fn read_booklet(s: &str) -> Vec<Instruction> {
-    s.lines().map(|line| Instruction::from(line)).collect()
+fn signal_on<W: Word>(wires: &Wires<W>, wire: &str) -> io::Result<W> {
+    wires
+        .inner
+        .get(wire)
+        .copied()
+        .ok_or_else(|| invalid_data(format!("no wire {}", wire)))
+}
+
+const VALUE_FLAGS: [&str; 7] = [
+    "--bits",
+    "--set",
+    "--dot",
+    "--rust",
+    "--optimise",
+    "--input",
+    "--explain",
+];
+
+/// Reads a `--set` value like `b=3176`.
+fn parse_setting<W: Word>(setting: &str) -> io::Result<(&str, W)> {
+    setting
+        .split_once('=')
+        .and_then(|(wire, value)| Some((wire, value.parse().ok()?)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("bad --set {:?}, expected WIRE=VALUE", setting),
+            )
+        })
+}
+
+fn parse_booklet<W: Word>(s: &str) -> Result<Vec<Instruction<'_, W>>, ParseError<'_>> {
+    s.lines().map(Instruction::from).collect()
+}
+
+/// The puzzle's booklet, with 16-bit wires.
+#[cfg(test)]
+fn read_booklet(s: &str) -> Vec<Instruction<'_>> {
+    parse_booklet(s).unwrap()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseError<'a> {
+    /// A line without `->`.
+    NoOutput(&'a str),
+    UnknownGate(&'a str),
+    /// A line whose shift or rotate amount is not a number.
+    BadAmount(&'a str),
+    /// A number too large for the wire width, which is never a wire name.
+    LiteralTooWide {
+        literal: &'a str,
+        word: &'static str,
+    },
+}
+
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::NoOutput(line) => write!(f, "no output wire in {:?}", line),
+            ParseError::UnknownGate(line) => write!(f, "unknown gate in {:?}", line),
+            ParseError::BadAmount(line) => write!(f, "bad amount in {:?}", line),
+            ParseError::LiteralTooWide { literal, word } => {
+                write!(f, "literal {} does not fit in {}", literal, word)
+            }
+        }
+    }
+}
+
+/// A gate input: another wire's signal, or a fixed value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand<'a, W: Word = u16> {
+    Wire(&'a str),
+    Literal(W),
 }
 
-#[derive(Debug, PartialEq)]
-enum Instruction<'a> {
-    Literal(u16, &'a str),
-    And(&'a str, &'a str, &'a str),
-    Or(&'a str, &'a str, &'a str),
-    Not(&'a str, &'a str),
-    Rshift(&'a str, u8, &'a str),
-    Lshift(&'a str, u8, &'a str),
+impl<'a, W: Word> Operand<'a, W> {
+    /// A token made only of digits is a literal, and anything else a wire.
+    fn from(s: &'a str) -> Result<Self, ParseError<'a>> {
+        if s.is_empty() || !s.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Ok(Self::Wire(s));
+        }
+        match s.parse() {
+            Ok(literal) => Ok(Self::Literal(literal)),
+            Err(_) => Err(ParseError::LiteralTooWide {
+                literal: s,
+                word: W::NAME,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Instruction<'a, W: Word = u16> {
+    Literal(W, &'a str),
+    And(Operand<'a, W>, Operand<'a, W>, &'a str),
+    Or(Operand<'a, W>, Operand<'a, W>, &'a str),
+    Xor(Operand<'a, W>, Operand<'a, W>, &'a str),
+    Nand(Operand<'a, W>, Operand<'a, W>, &'a str),
+    Nor(Operand<'a, W>, Operand<'a, W>, &'a str),
+    Not(Operand<'a, W>, &'a str),
+    Rshift(Operand<'a, W>, u8, &'a str),
+    Lshift(Operand<'a, W>, u8, &'a str),
+    Rrotate(Operand<'a, W>, u8, &'a str),
+    Lrotate(Operand<'a, W>, u8, &'a str),
+    /// A selector, the input for its clear bits, and the input for its set
+    /// bits.
+    Mux(Operand<'a, W>, Operand<'a, W>, Operand<'a, W>, &'a str),
     Move(&'a str, &'a str),
 }
 
-impl<'a> Instruction<'a> {
-    fn from(s: &'a str) -> Self {
-        let mut parts = s.split(' ');
-        if s.contains("AND") {
-            let input1 = parts.next().unwrap();
-            parts.next(); // skip AND
-            let input2 = parts.next().unwrap();
-            parts.next(); // skip ->
-            let output = parts.next().unwrap();
-            Self::And(input1, input2, output)
-        } else if s.contains("OR") {
-            let input1 = parts.next().unwrap();
-            parts.next(); // skip OR
-            let input2 = parts.next().unwrap();
-            parts.next(); // skip ->
-            let output = parts.next().unwrap();
-            Self::Or(input1, input2, output)
-        } else if s.contains("RSHIFT") {
-            let input = parts.next().unwrap();
-            parts.next(); // skip RSHIFT
-            let amount = parts.next().unwrap().parse().unwrap();
-            parts.next(); // skip ->
-            let output = parts.next().unwrap();
-            Self::Rshift(input, amount, output)
-        } else if s.contains("LSHIFT") {
-            let input = parts.next().unwrap();
-            parts.next(); // skip LSHIFT
-            let amount = parts.next().unwrap().parse().unwrap();
-            parts.next(); // skip ->
-            let output = parts.next().unwrap();
-            Self::Lshift(input, amount, output)
-        } else if s.contains("NOT") {
-            parts.next(); // skip NOT
-            let input = parts.next().unwrap();
-            parts.next(); // skip ->
-            let output = parts.next().unwrap();
-            Self::Not(input, output)
-        } else {
-            // LITERAL and MOVE
-            let input = parts.next().unwrap();
-            parts.next(); // skip ->
-            let output = parts.next().unwrap();
-            if let Ok(literal) = input.parse() {
-                Self::Literal(literal, output)
-            } else {
-                Self::Move(input, output)
+impl<'a, W: Word> Instruction<'a, W> {
+    /// Reads a line like `x AND y -> z`, `NOT x -> z` or `MUX s x y -> z`.
+    fn from(s: &'a str) -> Result<Self, ParseError<'a>> {
+        let (gate, output) = s.split_once(" -> ").ok_or(ParseError::NoOutput(s))?;
+        let tokens: Vec<&str> = gate.split(' ').collect();
+        let operand = |i: usize| Operand::from(tokens[i]);
+        let amount = || tokens[2].parse().map_err(|_| ParseError::BadAmount(s));
+        let instruction = match tokens[..] {
+            [input] => match Operand::from(input)? {
+                Operand::Literal(literal) => Self::Literal(literal, output),
+                Operand::Wire(input) => Self::Move(input, output),
+            },
+            ["NOT", _] => Self::Not(operand(1)?, output),
+            ["MUX", _, _, _] => Self::Mux(operand(1)?, operand(2)?, operand(3)?, output),
+            [_, "AND", _] => Self::And(operand(0)?, operand(2)?, output),
+            [_, "OR", _] => Self::Or(operand(0)?, operand(2)?, output),
+            [_, "XOR", _] => Self::Xor(operand(0)?, operand(2)?, output),
+            [_, "NAND", _] => Self::Nand(operand(0)?, operand(2)?, output),
+            [_, "NOR", _] => Self::Nor(operand(0)?, operand(2)?, output),
+            [_, "RSHIFT", _] => Self::Rshift(operand(0)?, amount()?, output),
+            [_, "LSHIFT", _] => Self::Lshift(operand(0)?, amount()?, output),
+            [_, "RROTATE", _] => Self::Rrotate(operand(0)?, amount()?, output),
+            [_, "LROTATE", _] => Self::Lrotate(operand(0)?, amount()?, output),
+            _ => return Err(ParseError::UnknownGate(s)),
+        };
+        Ok(instruction)
+    }
+}
+
+impl<'a, W: Word> Instruction<'a, W> {
+    /// The wire this instruction gives a signal to.
+    fn output(&self) -> &'a str {
+        match *self {
+            Instruction::Literal(_, out)
+            | Instruction::And(_, _, out)
+            | Instruction::Or(_, _, out)
+            | Instruction::Xor(_, _, out)
+            | Instruction::Nand(_, _, out)
+            | Instruction::Nor(_, _, out)
+            | Instruction::Not(_, out)
+            | Instruction::Rshift(_, _, out)
+            | Instruction::Lshift(_, _, out)
+            | Instruction::Rrotate(_, _, out)
+            | Instruction::Lrotate(_, _, out)
+            | Instruction::Mux(_, _, _, out)
+            | Instruction::Move(_, out) => out,
+        }
+    }
+
+    /// The wires this instruction needs signals from.
+    fn inputs(&self) -> Vec<&'a str> {
+        let operands = match *self {
+            Instruction::Literal(..) => vec![],
+            Instruction::And(in1, in2, _)
+            | Instruction::Or(in1, in2, _)
+            | Instruction::Xor(in1, in2, _)
+            | Instruction::Nand(in1, in2, _)
+            | Instruction::Nor(in1, in2, _) => vec![in1, in2],
+            Instruction::Not(input, _)
+            | Instruction::Rshift(input, _, _)
+            | Instruction::Lshift(input, _, _)
+            | Instruction::Rrotate(input, _, _)
+            | Instruction::Lrotate(input, _, _) => vec![input],
+            Instruction::Mux(select, in1, in2, _) => vec![select, in1, in2],
+            Instruction::Move(input, _) => vec![Operand::Wire(input)],
+        };
+        operands
+            .into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Wire(wire) => Some(wire),
+                Operand::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    /// The signal this instruction gives its wire, or `None` if `signal`
+    /// does not know one of its inputs.
+    fn evaluate(&self, signal: impl Fn(Operand<'a, W>) -> Option<W>) -> Option<W> {
+        let value = match *self {
+            Instruction::Literal(value, _) => value,
+            Instruction::And(in1, in2, _) => signal(in1)? & signal(in2)?,
+            Instruction::Or(in1, in2, _) => signal(in1)? | signal(in2)?,
+            Instruction::Xor(in1, in2, _) => signal(in1)? ^ signal(in2)?,
+            Instruction::Nand(in1, in2, _) => !(signal(in1)? & signal(in2)?),
+            Instruction::Nor(in1, in2, _) => !(signal(in1)? | signal(in2)?),
+            Instruction::Not(input, _) => !signal(input)?,
+            Instruction::Rshift(input, amount, _) => signal(input)?.shr(amount.into()),
+            Instruction::Lshift(input, amount, _) => signal(input)?.shl(amount.into()),
+            Instruction::Rrotate(input, amount, _) => signal(input)?.rotate_right(amount.into()),
+            Instruction::Lrotate(input, amount, _) => signal(input)?.rotate_left(amount.into()),
+            Instruction::Mux(select, in1, in2, _) => {
+                signal(select)?.select(signal(in1)?, signal(in2)?)
+            }
+            Instruction::Move(input, _) => signal(Operand::Wire(input))?,
+        };
+        Some(value)
+    }
+}
+
+impl<W: Word> fmt::Display for Operand<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Wire(wire) => write!(f, "{}", wire),
+            Operand::Literal(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// The instruction as it would be written in the booklet.
+impl<W: Word> fmt::Display for Instruction<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Literal(value, _) => write!(f, "{}", value)?,
+            Instruction::And(in1, in2, _) => write!(f, "{} AND {}", in1, in2)?,
+            Instruction::Or(in1, in2, _) => write!(f, "{} OR {}", in1, in2)?,
+            Instruction::Xor(in1, in2, _) => write!(f, "{} XOR {}", in1, in2)?,
+            Instruction::Nand(in1, in2, _) => write!(f, "{} NAND {}", in1, in2)?,
+            Instruction::Nor(in1, in2, _) => write!(f, "{} NOR {}", in1, in2)?,
+            Instruction::Not(input, _) => write!(f, "NOT {}", input)?,
+            Instruction::Rshift(input, amount, _) => write!(f, "{} RSHIFT {}", input, amount)?,
+            Instruction::Lshift(input, amount, _) => write!(f, "{} LSHIFT {}", input, amount)?,
+            Instruction::Rrotate(input, amount, _) => write!(f, "{} RROTATE {}", input, amount)?,
+            Instruction::Lrotate(input, amount, _) => write!(f, "{} LROTATE {}", input, amount)?,
+            Instruction::Mux(select, in1, in2, _) => write!(f, "MUX {} {} {}", select, in1, in2)?,
+            Instruction::Move(input, _) => write!(f, "{}", input)?,
+        }
+        write!(f, " -> {}", self.output())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum CircuitError<'a> {
+    /// A wire that is read but never given a signal, and a wire reading it.
+    Undefined { wire: &'a str, read_by: &'a str },
+    /// Wires that each feed the next, ending with the one they started from.
+    Cycle(Vec<&'a str>),
+    /// A wire given a signal by more than one instruction.
+    MultipleSources(&'a str),
+}
+
+impl fmt::Display for CircuitError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CircuitError::Undefined { wire, read_by } => {
+                write!(f, "wire {} is read by {} but has no signal", wire, read_by)
+            }
+            CircuitError::Cycle(wires) => write!(f, "wires form a loop: {}", wires.join(" -> ")),
+            CircuitError::MultipleSources(wire) => {
+                write!(f, "wire {} has more than one source", wire)
+            }
+        }
+    }
+}
+
+/// Orders the booklet so every wire comes after the wires it reads.
+fn dependency_order<'a, W: Word>(
+    booklet: &'a [Instruction<'a, W>],
+) -> Result<Vec<&'a Instruction<'a, W>>, CircuitError<'a>> {
+    let mut sources = HashMap::new();
+    for instruction in booklet {
+        if sources.insert(instruction.output(), instruction).is_some() {
+            return Err(CircuitError::MultipleSources(instruction.output()));
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+    let mut marks = HashMap::new();
+    let mut order = Vec::with_capacity(booklet.len());
+
+    for instruction in booklet {
+        if marks.contains_key(instruction.output()) {
+            continue;
+        }
+        // depth first, keeping the path so a loop can be reported in full
+        let mut path = vec![(instruction.output(), instruction.inputs(), 0)];
+        marks.insert(instruction.output(), Mark::Visiting);
+        while let Some((wire, inputs, next)) = path.last_mut() {
+            let wire = *wire;
+            let input = match inputs.get(*next) {
+                Some(input) => *input,
+                None => {
+                    marks.insert(wire, Mark::Done);
+                    order.push(sources[wire]);
+                    path.pop();
+                    continue;
+                }
+            };
+            *next += 1;
+
+            let source = match sources.get(input) {
+                Some(source) => source,
+                None => {
+                    return Err(CircuitError::Undefined {
+                        wire: input,
+                        read_by: wire,
+                    })
+                }
+            };
+            match marks.get(input) {
+                Some(Mark::Done) => {}
+                Some(Mark::Visiting) => {
+                    let start = path.iter().position(|(w, _, _)| *w == input).unwrap();
+                    let mut cycle: Vec<&str> = path[start..].iter().map(|(w, _, _)| *w).collect();
+                    cycle.push(input);
+                    // the path follows reads; signals flow the other way
+                    cycle.reverse();
+                    return Err(CircuitError::Cycle(cycle));
+                }
+                None => {
+                    marks.insert(input, Mark::Visiting);
+                    path.push((input, source.inputs(), 0));
+                }
             }
         }
     }
+
+    Ok(order)
 }
 
-struct Wires<'a> {
-    inner: HashMap<&'a str, u16>,
+/// A wire whose signal was changed by pinning.
+#[derive(Debug, PartialEq, Eq)]
+struct Change<'a, W: Word = u16> {
+    wire: &'a str,
+    before: W,
+    after: W,
 }
 
-impl<'a> Wires<'a> {
+struct Wires<'a, W: Word = u16> {
+    inner: HashMap<&'a str, W>,
+    /// The booklet last run, in dependency order.
+    order: Vec<&'a Instruction<'a, W>>,
+    /// Wires held at a signal whatever their source says.
+    pinned: HashMap<&'a str, W>,
+}
+
+impl<'a, W: Word> Wires<'a, W> {
     fn new() -> Self {
         Self {
             inner: HashMap::new(),
+            order: Vec::new(),
+            pinned: HashMap::new(),
         }
     }
 
-    fn run_booklet(&mut self, booklet: &'a Vec<Instruction>) {
-        let mut remaining: HashSet<usize> = (0..booklet.len()).collect();
-        while !remaining.is_empty() {
-            for (i, instruction) in booklet.iter().enumerate() {
-                if remaining.contains(&i) && self.do_instruction(instruction).is_ok() {
-                    remaining.remove(&i);
-                }
+    /// Gives every wire its signal, or sets none if the booklet is broken.
+    fn run_booklet(&mut self, booklet: &'a [Instruction<'a, W>]) -> Result<(), CircuitError<'a>> {
+        let order = dependency_order(booklet)?;
+        for instruction in &order {
+            self.evaluate(instruction);
+        }
+        self.order = order;
+        Ok(())
+    }
+
+    /// Holds a wire of the last booklet run at `value`, recomputing only the
+    /// wires downstream of it. Returns the wires that changed in dependency
+    /// order, or `None` if no instruction gives the wire a signal.
+    fn pin(&mut self, wire: &str, value: W) -> Option<Vec<Change<'a, W>>> {
+        let start = self.order.iter().position(|i| i.output() == wire)?;
+        let wire = self.order[start].output();
+        self.pinned.insert(wire, value);
+
+        let mut changes = Vec::new();
+        let mut changed = HashSet::new();
+        let order = std::mem::take(&mut self.order);
+        for &instruction in &order[start..] {
+            let output = instruction.output();
+            let affected = output == wire
+                || instruction
+                    .inputs()
+                    .iter()
+                    .any(|input| changed.contains(input));
+            if !affected {
+                continue;
             }
-            if remaining.len() == 320 {
-                println!("{:?}", self.inner);
+            let before = self.inner[output];
+            self.evaluate(instruction);
+            let after = self.inner[output];
+            if before != after {
+                changed.insert(output);
+                changes.push(Change {
+                    wire: output,
+                    before,
+                    after,
+                });
+            }
+        }
+        self.order = order;
+        Some(changes)
+    }
 
-                panic!("inf loop");
+    /// Gives an instruction's wire its signal, unless the wire is pinned.
+    fn evaluate(&mut self, instruction: &'a Instruction<'a, W>) {
+        match self.pinned.get(instruction.output()) {
+            Some(&value) => {
+                self.inner.insert(instruction.output(), value);
             }
+            None => self
+                .do_instruction(instruction)
+                .expect("inputs are set before they are read"),
         }
     }
 
-    fn do_instruction(&mut self, instruction: &'a Instruction<'a>) -> Result<(), &'a str> {
-        match instruction {
-            Instruction::And(in1, in2, out) => self.inner.insert(
-                out,
-                *self.inner.get(in1).ok_or("not set yet")?
-                    & *self.inner.get(in2).ok_or("not set yet")?,
-            ),
-            Instruction::Or(in1, in2, out) => self.inner.insert(
-                out,
-                *self.inner.get(in1).ok_or("not set yet")?
-                    | *self.inner.get(in2).ok_or("not set yet")?,
-            ),
-            Instruction::Rshift(input, amount, out) => self
-                .inner
-                .insert(out, *self.inner.get(input).ok_or("not set yet")? >> amount),
-            Instruction::Lshift(input, amount, out) => self
-                .inner
-                .insert(out, *self.inner.get(input).ok_or("not set yet")? << amount),
-            Instruction::Not(input, out) => self
-                .inner
-                .insert(out, !*self.inner.get(input).ok_or("not set yet")?),
-            Instruction::Literal(value, name) => self.inner.insert(name, *value),
-            Instruction::Move(input, output) => self
-                .inner
-                .insert(output, *self.inner.get(input).ok_or("not set yet")?),
-        };
+    fn do_instruction(&mut self, instruction: &'a Instruction<'a, W>) -> Result<(), &'a str> {
+        let signal = instruction
+            .evaluate(|operand| match operand {
+                Operand::Wire(wire) => self.inner.get(wire).copied(),
+                Operand::Literal(value) => Some(value),
+            })
+            .ok_or("not set yet")?;
+        self.inner.insert(instruction.output(), signal);
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::symbolic::Expr;
     use super::*;
 
+    use util::Lcg;
+
     #[test]
     fn test_instruction_from() {
-        let instruction = Instruction::from("123 -> x");
+        let instruction: Instruction = Instruction::from("123 -> x").unwrap();
         assert_eq!(instruction, Instruction::Literal(123, "x"));
 
-        let instruction = Instruction::from("x AND y -> d");
-        assert_eq!(instruction, Instruction::And("x", "y", "d"));
+        let instruction: Instruction = Instruction::from("x AND y -> d").unwrap();
+        assert_eq!(
+            instruction,
+            Instruction::And(Operand::Wire("x"), Operand::Wire("y"), "d")
+        );
 
-        let instruction = Instruction::from("x OR y -> e");
-        assert_eq!(instruction, Instruction::Or("x", "y", "e"));
+        let instruction: Instruction = Instruction::from("x OR y -> e").unwrap();
+        assert_eq!(
+            instruction,
+            Instruction::Or(Operand::Wire("x"), Operand::Wire("y"), "e")
+        );
 
-        let instruction = Instruction::from("x LSHIFT 2 -> f");
-        assert_eq!(instruction, Instruction::Lshift("x", 2, "f"));
+        let instruction: Instruction = Instruction::from("x LSHIFT 2 -> f").unwrap();
+        assert_eq!(instruction, Instruction::Lshift(Operand::Wire("x"), 2, "f"));
 
-        let instruction = Instruction::from("y RSHIFT 2 -> g");
-        assert_eq!(instruction, Instruction::Rshift("y", 2, "g"));
+        let instruction: Instruction = Instruction::from("y RSHIFT 2 -> g").unwrap();
+        assert_eq!(instruction, Instruction::Rshift(Operand::Wire("y"), 2, "g"));
 
-        let instruction = Instruction::from("NOT x -> h");
-        assert_eq!(instruction, Instruction::Not("x", "h"));
+        let instruction: Instruction = Instruction::from("NOT x -> h").unwrap();
+        assert_eq!(instruction, Instruction::Not(Operand::Wire("x"), "h"));
 
-        let instruction = Instruction::from("NOT y -> i");
-        assert_eq!(instruction, Instruction::Not("y", "i"));
+        let instruction: Instruction = Instruction::from("NOT y -> i").unwrap();
+        assert_eq!(instruction, Instruction::Not(Operand::Wire("y"), "i"));
 
-        let instruction = Instruction::from("y -> i");
+        let instruction: Instruction = Instruction::from("y -> i").unwrap();
         assert_eq!(instruction, Instruction::Move("y", "i"));
+
+        let instruction: Instruction = Instruction::from("1 AND cx -> cy").unwrap();
+        assert_eq!(
+            instruction,
+            Instruction::And(Operand::Literal(1), Operand::Wire("cx"), "cy")
+        );
+
+        let instruction: Instruction = Instruction::from("NOT 0 -> z").unwrap();
+        assert_eq!(instruction, Instruction::Not(Operand::Literal(0), "z"));
+
+        let instruction: Instruction = Instruction::from("65535 RSHIFT 8 -> lo").unwrap();
+        assert_eq!(
+            instruction,
+            Instruction::Rshift(Operand::Literal(65535), 8, "lo")
+        );
+    }
+
+    #[test]
+    fn test_more_gates() {
+        let booklet = read_booklet(
+            "x XOR y -> a
+x NAND y -> b
+x NOR y -> c
+x RROTATE 4 -> d
+x LROTATE 4 -> e
+MUX 255 x y -> f
+MUX s 0 65535 -> g
+x LSHIFT 16 -> h
+61680 -> s
+4660 -> x
+43981 -> y",
+        );
+        assert_eq!(
+            booklet[2],
+            Instruction::Nor(Operand::Wire("x"), Operand::Wire("y"), "c")
+        );
+        assert_eq!(booklet[3], Instruction::Rrotate(Operand::Wire("x"), 4, "d"));
+        assert_eq!(
+            booklet[5],
+            Instruction::Mux(
+                Operand::Literal(255),
+                Operand::Wire("x"),
+                Operand::Wire("y"),
+                "f"
+            )
+        );
+
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet).unwrap();
+        assert_eq!(wires.inner["a"], 0x1234 ^ 0xabcd);
+        assert_eq!(wires.inner["b"], !(0x1234 & 0xabcd));
+        assert_eq!(wires.inner["c"], !(0x1234 | 0xabcd));
+        assert_eq!(wires.inner["d"], 0x4123);
+        assert_eq!(wires.inner["e"], 0x2341);
+        assert_eq!(wires.inner["f"], 0x12cd);
+        assert_eq!(wires.inner["g"], 0xf0f0);
+        assert_eq!(wires.inner["h"], 0);
+    }
+
+    #[test]
+    fn test_word_widths() {
+        let s = "200 -> x\nx LSHIFT 1 -> y\nx LROTATE 1 -> z\nNOT x -> n";
+        let booklet = parse_booklet::<u8>(s).unwrap();
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet).unwrap();
+        assert_eq!(wires.inner["y"], 144);
+        assert_eq!(wires.inner["z"], 145);
+        assert_eq!(wires.inner["n"], 55);
+
+        let booklet = parse_booklet::<u64>(s).unwrap();
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet).unwrap();
+        assert_eq!(wires.inner["y"], 400);
+        assert_eq!(wires.inner["z"], 400);
+        assert_eq!(wires.inner["n"], u64::MAX - 200);
+
+        assert_eq!(
+            parse_booklet::<u8>("x AND 300 -> y"),
+            Err(ParseError::LiteralTooWide {
+                literal: "300",
+                word: "u8"
+            })
+        );
+        assert_eq!(
+            Instruction::<u8>::from("300 -> x").unwrap_err().to_string(),
+            "literal 300 does not fit in u8"
+        );
+        assert_eq!(
+            parse_booklet::<u8>("x LSHIFT y -> z"),
+            Err(ParseError::BadAmount("x LSHIFT y -> z"))
+        );
+        assert_eq!(
+            parse_booklet::<u8>("x SHUFFLE 1 -> z"),
+            Err(ParseError::UnknownGate("x SHUFFLE 1 -> z"))
+        );
+        assert_eq!(parse_booklet::<u8>("x"), Err(ParseError::NoOutput("x")));
+    }
+
+    #[test]
+    fn test_literal_operands() {
+        let s = "1 AND x -> a
+x OR 6 -> b
+NOT 0 -> c
+3 LSHIFT 4 -> d
+x -> e
+123 -> x";
+
+        let mut wires = Wires::new();
+        let booklet = read_booklet(s);
+        wires.run_booklet(&booklet).unwrap();
+        assert_eq!(wires.inner["a"], 1);
+        assert_eq!(wires.inner["b"], 127);
+        assert_eq!(wires.inner["c"], 65535);
+        assert_eq!(wires.inner["d"], 48);
+        assert_eq!(wires.inner["e"], 123);
     }
 
     #[test]
@@ -227,7 +879,7 @@ NOT y -> i";
 
         let mut wires = Wires::new();
         let booklet = read_booklet(s);
-        wires.run_booklet(&booklet);
+        wires.run_booklet(&booklet).unwrap();
 
         // d: 72
         assert_eq!(*wires.inner.get("d").unwrap(), 72);
@@ -267,7 +919,7 @@ NOT y -> i
 
         let mut wires = Wires::new();
         let booklet = read_booklet(s);
-        wires.run_booklet(&booklet);
+        wires.run_booklet(&booklet).unwrap();
 
         // d: 72
         assert_eq!(*wires.inner.get("d").unwrap(), 72);
@@ -293,4 +945,498 @@ NOT y -> i
         // y: 456
         assert_eq!(*wires.inner.get("y").unwrap(), 456);
     }
+
+    #[test]
+    fn test_broken_booklets() {
+        let booklet = read_booklet("x AND y -> d\n123 -> x");
+        assert_eq!(
+            Wires::new().run_booklet(&booklet),
+            Err(CircuitError::Undefined {
+                wire: "y",
+                read_by: "d"
+            })
+        );
+
+        let booklet = read_booklet("1 -> z\nz OR c -> a\na -> b\nNOT b -> c");
+        let error = Wires::new().run_booklet(&booklet).unwrap_err();
+        assert_eq!(error, CircuitError::Cycle(vec!["a", "b", "c", "a"]));
+        assert_eq!(error.to_string(), "wires form a loop: a -> b -> c -> a");
+
+        let booklet = read_booklet("x -> x");
+        assert_eq!(
+            Wires::new().run_booklet(&booklet),
+            Err(CircuitError::Cycle(vec!["x", "x"]))
+        );
+
+        let booklet = read_booklet("1 -> x\n2 -> x");
+        assert_eq!(
+            Wires::new().run_booklet(&booklet),
+            Err(CircuitError::MultipleSources("x"))
+        );
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // reversed so each wire is defined after the wire reading it
+        let mut lines: Vec<String> = (1..2000)
+            .map(|i| format!("w{} LSHIFT 1 -> w{}", i - 1, i))
+            .collect();
+        lines.push("1 -> w0".to_string());
+        lines.reverse();
+        let text = lines.join("\n");
+        let booklet = read_booklet(&text);
+
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet).unwrap();
+        assert_eq!(wires.inner["w15"], 1 << 15);
+        assert_eq!(wires.inner["w16"], 0);
+        assert_eq!(wires.inner.len(), 2000);
+    }
+
+    #[test]
+    fn test_pin() {
+        let s = "123 -> x
+456 -> y
+x AND y -> d
+x OR y -> e
+x LSHIFT 2 -> f
+y RSHIFT 2 -> g
+NOT x -> h
+NOT y -> i";
+
+        let mut wires = Wires::new();
+        let booklet = read_booklet(s);
+        wires.run_booklet(&booklet).unwrap();
+
+        let change = |wire, before, after| Change {
+            wire,
+            before,
+            after,
+        };
+        assert_eq!(
+            wires.pin("x", 0),
+            Some(vec![
+                change("x", 123, 0),
+                change("d", 72, 0),
+                change("e", 507, 456),
+                change("f", 492, 0),
+                change("h", 65412, 65535),
+            ])
+        );
+        assert_eq!(wires.inner["g"], 114);
+
+        // already at that signal
+        assert_eq!(wires.pin("y", 456), Some(vec![]));
+        assert_eq!(wires.pin("z", 1), None);
+
+        // pins hold when the booklet is run again
+        wires.run_booklet(&booklet).unwrap();
+        assert_eq!(wires.inner["x"], 0);
+        assert_eq!(wires.inner["e"], 456);
+    }
+
+    #[test]
+    fn test_pin_stops_where_signals_agree() {
+        let booklet = read_booklet("b AND 1 -> c\nc -> a\n2 -> b");
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet).unwrap();
+
+        // c is 0 before and after, so a is not recomputed
+        assert_eq!(
+            wires.pin("b", 4),
+            Some(vec![Change {
+                wire: "b",
+                before: 2,
+                after: 4
+            }])
+        );
+        assert_eq!(wires.pin("b", 5).unwrap().len(), 3);
+        assert_eq!(wires.inner["a"], 1);
+    }
+
+    #[test]
+    fn test_netlist() {
+        let booklet = read_booklet("x AND y -> d\nNOT d -> e\n123 -> x\n456 -> y\n1 OR x -> f");
+        let nets = netlist(&booklet).unwrap();
+        let net = |wire| nets.iter().find(|net| net.wire == wire).unwrap();
+
+        assert_eq!(net("x").fan_in, Vec::<&str>::new());
+        assert_eq!(net("x").fan_out, vec!["d", "f"]);
+        assert_eq!(net("x").depth, 0);
+        assert_eq!(net("d").fan_in, vec!["x", "y"]);
+        assert_eq!(net("d").depth, 1);
+        assert_eq!(net("e").depth, 2);
+        assert_eq!(net("f").fan_in, vec!["x"]);
+        assert_eq!(net("f").depth, 1);
+
+        let position = |wire| nets.iter().position(|net| net.wire == wire).unwrap();
+        assert!(position("x") < position("d") && position("d") < position("e"));
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let booklet = read_booklet("123 -> x\nx AND 1 -> d\nNOT d -> e\ny -> z");
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet[..3]).unwrap();
+
+        let mut out = Vec::new();
+        write_dot(&booklet, Some(&wires.inner), &mut out).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = dot.lines().collect();
+        assert_eq!(lines[0], "digraph booklet {");
+        assert!(lines.contains(&"    g0 [shape=box, label=\"123\"];"));
+        assert!(lines.contains(&"    g1 [shape=invhouse, label=\"AND 1\"];"));
+        assert!(lines.contains(&"    g2 [shape=invtriangle, label=\"NOT\"];"));
+        assert!(lines.contains(&"    g0 -> g1 [label=\"x = 123\"];"));
+        assert!(lines.contains(&"    g1 -> g2 [label=\"d = 1\"];"));
+        assert!(lines.contains(&"    g2 -> \"e\" [label=\"e = 65534\"];"));
+        // y has no source, and no signal to show
+        assert!(lines.contains(&"    \"y\" -> g3 [label=\"y\"];"));
+        assert_eq!(lines.last(), Some(&"}"));
+    }
+
+    /// Runs a booklet both ways and checks every wire agrees, with and
+    /// without each pin.
+    fn assert_compiled_matches<W: Word>(s: &str, pins: &[(&str, W)]) {
+        let booklet = parse_booklet::<W>(s).unwrap();
+        let program = Program::compile(&booklet).unwrap();
+        let mut wires = Wires::new();
+        wires.run_booklet(&booklet).unwrap();
+
+        let mut slots = vec![];
+        for &(wire, value) in std::iter::once(&("", W::default())).chain(pins) {
+            if !wire.is_empty() {
+                wires.pin(wire, value).unwrap();
+                slots.push((program.slot(wire).unwrap(), value));
+            }
+            let signals = program.run(&slots);
+            for (wire, signal) in &wires.inner {
+                let slot = program.slot(wire).unwrap();
+                assert_eq!(signals[slot as usize], *signal, "wire {}", wire);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compiled_matches_wires() {
+        let example = "123 -> x
+456 -> y
+x AND y -> d
+x OR y -> e
+x LSHIFT 2 -> f
+y RSHIFT 2 -> g
+NOT x -> h
+NOT y -> i";
+        assert_compiled_matches(example, &[("x", 7u16), ("g", 1), ("y", 65535)]);
+
+        let literals = "1 AND x -> a\nx OR 6 -> b\nNOT 0 -> c\n3 LSHIFT 4 -> d\nx -> e\n123 -> x";
+        assert_compiled_matches(literals, &[("x", 4u16), ("c", 9)]);
+    }
+
+    /// Random gate inputs for generated booklets.
+    trait Operands {
+        /// A literal below `max`, or one of the first `wires` wires.
+        fn operand(&mut self, wires: u64, max: u64) -> String;
+    }
+
+    impl Operands for Lcg {
+        fn operand(&mut self, wires: u64, max: u64) -> String {
+            if wires == 0 || self.below(5) == 0 {
+                self.below(max).to_string()
+            } else {
+                format!("w{}", self.below(wires))
+            }
+        }
+    }
+
+    /// Builds circuits of every gate on `W` wide wires, and checks they
+    /// compile to programs that agree with `Wires`.
+    fn assert_random_circuits_match<W: Word>(seed: u64) {
+        let max = 1 << W::BITS.min(31);
+        let mut rng = Lcg(seed);
+        for _ in 0..20 {
+            // each wire reads only wires before it, listed last first
+            let mut lines = vec![];
+            for i in 0..60 {
+                let gate = match rng.below(11) {
+                    0 => format!("{}", rng.below(max)),
+                    1 => format!("{} AND {}", rng.operand(i, max), rng.operand(i, max)),
+                    2 => format!("{} OR {}", rng.operand(i, max), rng.operand(i, max)),
+                    3 => format!("{} XOR {}", rng.operand(i, max), rng.operand(i, max)),
+                    4 => format!("{} NAND {}", rng.operand(i, max), rng.operand(i, max)),
+                    5 => format!("{} NOR {}", rng.operand(i, max), rng.operand(i, max)),
+                    6 => format!("NOT {}", rng.operand(i, max)),
+                    7 => format!(
+                        "MUX {} {} {}",
+                        rng.operand(i, max),
+                        rng.operand(i, max),
+                        rng.operand(i, max)
+                    ),
+                    n => {
+                        let op = ["RSHIFT", "LSHIFT", "RROTATE", "LROTATE"][rng.below(4) as usize];
+                        let amount = rng.below(W::BITS as u64 + 4);
+                        match n {
+                            8 if i > 0 => format!("w{}", rng.below(i)),
+                            _ => format!("{} {} {}", rng.operand(i, max), op, amount),
+                        }
+                    }
+                };
+                lines.push(format!("{} -> w{}", gate, i));
+            }
+            lines.reverse();
+
+            let pins: Vec<(String, W)> = (0..3)
+                .map(|_| {
+                    let value = rng.below(max).to_string().parse().ok().unwrap();
+                    (format!("w{}", rng.below(60)), value)
+                })
+                .collect();
+            let pins: Vec<(&str, W)> = pins
+                .iter()
+                .map(|(wire, value)| (wire.as_str(), *value))
+                .collect();
+            assert_compiled_matches(&lines.join("\n"), &pins);
+        }
+    }
+
+    #[test]
+    fn test_compiled_matches_wires_on_random_circuits() {
+        assert_random_circuits_match::<u8>(7);
+        assert_random_circuits_match::<u16>(8);
+        assert_random_circuits_match::<u32>(9);
+        assert_random_circuits_match::<u64>(10);
+    }
+
+    #[test]
+    fn test_to_rust() {
+        let booklet = read_booklet("if AND 1 -> in\n3 -> if\nin -> a");
+        let program = Program::compile(&booklet).unwrap();
+        assert_eq!(
+            program.to_rust("circuit"),
+            "pub const WIRES: [&str; 3] = [\"if\", \"in\", \"a\"];
+
+pub fn circuit() -> [u16; 3] {
+    let w_if: u16 = 3;
+    let w_in: u16 = w_if & 1u16;
+    let w_a: u16 = w_in;
+    [w_if, w_in, w_a]
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let s = "123 -> x
+1 AND x -> a
+x OR y -> b
+x XOR 7 -> c
+x NAND y -> d
+x NOR y -> e
+NOT x -> f
+x RSHIFT 2 -> g
+x LSHIFT 3 -> h
+x RROTATE 4 -> i
+x LROTATE 5 -> j
+MUX x 0 y -> k
+j -> y";
+        let booklet = read_booklet(s);
+        let text: Vec<String> = booklet.iter().map(|i| i.to_string()).collect();
+        assert_eq!(text.join("\n"), s);
+    }
+
+    #[test]
+    fn test_optimise_folds_example() {
+        let booklet = read_booklet(
+            "123 -> x
+456 -> y
+x AND y -> d
+x OR y -> e
+NOT x -> h",
+        );
+        let (reduced, stats) = optimise(&booklet, "d", &[]).unwrap();
+        assert_eq!(reduced, vec![Instruction::Literal(72, "d")]);
+        assert_eq!(
+            stats,
+            optimise::Stats {
+                instructions: 5,
+                folded: 3,
+                simplified: 0,
+                removed: 4
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "5 instructions: 3 folded, 0 simplified, 4 removed, 1 left"
+        );
+    }
+
+    #[test]
+    fn test_optimise_simplifies_identities() {
+        let booklet = read_booklet(
+            "x AND 65535 -> a
+NOT a -> b
+NOT b -> c
+c OR 0 -> d
+d -> e
+e XOR e -> f
+f OR e -> g
+5 -> x
+9 -> y",
+        );
+        let (reduced, stats) = optimise(&booklet, "g", &["x"]).unwrap();
+        let text: Vec<String> = reduced.iter().map(|i| i.to_string()).collect();
+        assert_eq!(text, vec!["5 -> x", "x -> g"]);
+        assert_eq!(stats.folded, 1);
+        assert_eq!(stats.removed, 7);
+
+        // kept only because the output reads it
+        let (reduced, _) = optimise(&booklet, "b", &["x"]).unwrap();
+        let text: Vec<String> = reduced.iter().map(|i| i.to_string()).collect();
+        assert_eq!(text, vec!["5 -> x", "NOT x -> b"]);
+    }
+
+    #[test]
+    fn test_optimise_stops_at_free_inputs() {
+        let booklet = read_booklet("NOT y -> a\nNOT a -> b\n5 -> y");
+        let (reduced, _) = optimise(&booklet, "b", &["a"]).unwrap();
+        let text: Vec<String> = reduced.iter().map(|i| i.to_string()).collect();
+        assert_eq!(text, vec!["5 -> y", "NOT y -> a", "NOT a -> b"]);
+
+        let mut wires = Wires::new();
+        wires.run_booklet(&reduced).unwrap();
+        wires.pin("a", 0);
+        assert_eq!(wires.inner["b"], 65535);
+    }
+
+    #[test]
+    fn test_optimise_keeps_signals() {
+        let mut rng = Lcg(11);
+        for _ in 0..50 {
+            let mut lines = vec![];
+            for i in 0..40 {
+                let gate = match rng.below(9) {
+                    0 => format!("{}", [0, 65535, rng.below(65536)][rng.below(3) as usize]),
+                    1 => format!("{} AND {}", rng.operand(i, 65536), rng.operand(i, 65536)),
+                    2 => format!("{} OR {}", rng.operand(i, 65536), rng.operand(i, 65536)),
+                    3 => format!("{} XOR {}", rng.operand(i, 65536), rng.operand(i, 65536)),
+                    4 => format!("{} NAND {}", rng.operand(i, 65536), rng.operand(i, 65536)),
+                    5 => format!("NOT {}", rng.operand(i, 65536)),
+                    6 => format!("{} RSHIFT {}", rng.operand(i, 65536), rng.below(18)),
+                    7 => format!(
+                        "MUX {} {} {}",
+                        rng.operand(i, 65536),
+                        rng.operand(i, 65536),
+                        rng.operand(i, 65536)
+                    ),
+                    _ => rng.operand(i, 65536),
+                };
+                lines.push(format!("{} -> w{}", gate, i));
+            }
+            let text = lines.join("\n");
+            let booklet = read_booklet(&text);
+            let inputs = [format!("w{}", rng.below(10)), format!("w{}", rng.below(20))];
+            let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+            let output = "w39";
+            let (reduced, _) = optimise(&booklet, output, &inputs).unwrap();
+
+            for _ in 0..5 {
+                let pins: Vec<u16> = inputs.iter().map(|_| rng.below(65536) as u16).collect();
+                let mut full = Wires::new();
+                full.run_booklet(&booklet).unwrap();
+                let mut small = Wires::new();
+                small.run_booklet(&reduced).unwrap();
+                for (input, pin) in inputs.iter().zip(&pins) {
+                    full.pin(input, *pin);
+                    small.pin(input, *pin);
+                }
+                assert_eq!(small.inner[output], full.inner[output], "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_symbolic() {
+        let booklet = read_booklet(
+            "123 -> x
+456 -> y
+x AND y -> d
+x OR y -> e
+y RSHIFT 2 -> g
+NOT x -> h
+NOT h -> k
+k AND 65535 -> m",
+        );
+        let symbolic = Symbolic::new(&booklet, &["x"]).unwrap();
+        let sources = |wire| {
+            symbolic
+                .sources(wire)
+                .unwrap()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sources("d"), vec!["x", "y"]);
+        assert_eq!(sources("g"), vec!["y"]);
+        assert_eq!(sources("m"), vec!["x"]);
+
+        let expression = |wire| symbolic.expression(wire).unwrap().to_string();
+        assert_eq!(expression("d"), "x AND 456");
+        assert_eq!(expression("e"), "x OR 456");
+        assert_eq!(expression("g"), "114");
+        assert_eq!(expression("h"), "NOT x");
+        assert_eq!(expression("m"), "x");
+        assert_eq!(symbolic.expression("m"), Some(&Expr::Input("x")));
+        assert!(symbolic.expression("g").unwrap().inputs().is_empty());
+
+        assert_eq!(symbolic.affected_by("x"), vec!["d", "e", "h", "k", "m"]);
+        assert_eq!(symbolic.affected_by("y"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_symbolic_input_between_nots() {
+        let booklet = read_booklet("NOT y -> a\nNOT a -> b\n5 -> y");
+        let symbolic = Symbolic::new(&booklet, &["a"]).unwrap();
+        assert_eq!(symbolic.expression("b").unwrap().to_string(), "NOT a");
+        assert_eq!(symbolic.expression("a"), Some(&Expr::Input("a")));
+        assert_eq!(symbolic.affected_by("a"), vec!["b"]);
+        assert_eq!(symbolic.affected_by("y"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_symbolic_definitions() {
+        let booklet = read_booklet("1 -> x\nx RSHIFT 1 -> p\np OR x -> q\np AND q -> r\nr -> a");
+        let symbolic = Symbolic::new(&booklet, &["x"]).unwrap();
+        assert_eq!(
+            symbolic.expression("a").unwrap().to_string(),
+            "(x RSHIFT 1) AND ((x RSHIFT 1) OR x)"
+        );
+        assert_eq!(
+            symbolic.definitions("a").unwrap(),
+            vec![
+                ("p", "x RSHIFT 1".to_string()),
+                ("r", "p AND (p OR x)".to_string()),
+                ("a", "r".to_string())
+            ]
+        );
+        assert_eq!(
+            symbolic.definitions("x").unwrap(),
+            vec![("x", "x".to_string())]
+        );
+        assert_eq!(symbolic.definitions("z"), None);
+
+        let symbolic = Symbolic::new(&booklet, &[]).unwrap();
+        assert_eq!(
+            symbolic.definitions("a").unwrap(),
+            vec![("a", "0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_setting() {
+        assert_eq!(parse_setting::<u16>("b=3176").unwrap(), ("b", 3176));
+        assert!(parse_setting::<u16>("b").is_err());
+        assert!(parse_setting::<u16>("b=-1").is_err());
+        assert!(parse_setting::<u16>("b=65536").is_err());
+    }
 }
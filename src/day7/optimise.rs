@@ -0,0 +1,208 @@
+/*!
+Shrinking the booklet before running it.
+
+One pass in dependency order rewrites every instruction: wires with a known
+signal are replaced by literals, wires that only copy another are replaced by
+their source, gates whose inputs are all literals fold into a literal, and
+gates that do not need every input are simplified, such as `x AND 65535` or a
+`NOT` of a `NOT`. A second pass keeps only the instructions the requested
+output reads from, directly or through other wires.
+
+Free inputs are wires that keep their instruction but are never folded, so
+the reduced booklet still follows them when they are pinned.
+*/
+
+use super::{dependency_order, CircuitError, Instruction, Operand, Word};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// How much of the booklet an optimisation removed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub instructions: usize,
+    /// Wires found to carry a constant signal.
+    pub folded: usize,
+    /// Gates rewritten into a simpler gate or a copy.
+    pub simplified: usize,
+    /// Instructions the output does not read from.
+    pub removed: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} instructions: {} folded, {} simplified, {} removed, {} left",
+            self.instructions,
+            self.folded,
+            self.simplified,
+            self.removed,
+            self.instructions - self.removed
+        )
+    }
+}
+
+/// The instructions `output` needs, in dependency order, treating `inputs`
+/// as wires whose signal is not known in advance.
+pub fn optimise<'a, W: Word>(
+    booklet: &'a [Instruction<'a, W>],
+    output: &str,
+    inputs: &[&str],
+) -> Result<(Vec<Instruction<'a, W>>, Stats), CircuitError<'a>> {
+    let (mut reduced, mut stats) = rewrite(booklet, inputs)?;
+
+    let mut needed = HashSet::new();
+    needed.insert(output);
+    for instruction in reduced.iter().rev() {
+        if needed.contains(instruction.output()) {
+            needed.extend(instruction.inputs());
+        }
+    }
+    reduced.retain(|instruction| needed.contains(instruction.output()));
+    stats.removed = booklet.len() - reduced.len();
+
+    Ok((reduced, stats))
+}
+
+/// Every instruction rewritten in dependency order, before any are removed.
+pub fn rewrite<'a, W: Word>(
+    booklet: &'a [Instruction<'a, W>],
+    inputs: &[&str],
+) -> Result<(Vec<Instruction<'a, W>>, Stats), CircuitError<'a>> {
+    let mut stats = Stats {
+        instructions: booklet.len(),
+        ..Stats::default()
+    };
+    // what a wire can be replaced by: its literal signal, or the wire it copies
+    let mut replacements: HashMap<&str, Operand<W>> = HashMap::new();
+    // what simplify may look through; free inputs are left out, as their
+    // instruction may not give them their signal
+    let mut rewritten: HashMap<&str, Instruction<W>> = HashMap::new();
+    let mut reduced = Vec::with_capacity(booklet.len());
+
+    for instruction in dependency_order(booklet)? {
+        let wire = instruction.output();
+        let result = if inputs.contains(&wire) {
+            *instruction
+        } else {
+            let substituted = substitute(*instruction, |operand| match operand {
+                Operand::Wire(input) => replacements.get(input).copied().unwrap_or(operand),
+                Operand::Literal(_) => operand,
+            });
+            let simplified = simplify(substituted, &rewritten);
+            let literal = simplified.evaluate(|operand| match operand {
+                Operand::Wire(_) => None,
+                Operand::Literal(value) => Some(value),
+            });
+            match literal {
+                Some(value) => {
+                    if !matches!(instruction, Instruction::Literal(..)) {
+                        stats.folded += 1;
+                    }
+                    replacements.insert(wire, Operand::Literal(value));
+                    Instruction::Literal(value, wire)
+                }
+                None => {
+                    if simplified != substituted {
+                        stats.simplified += 1;
+                    }
+                    if let Instruction::Move(source, _) = simplified {
+                        replacements.insert(wire, Operand::Wire(source));
+                    }
+                    simplified
+                }
+            }
+        };
+        if !inputs.contains(&wire) {
+            rewritten.insert(wire, result);
+        }
+        reduced.push(result);
+    }
+    Ok((reduced, stats))
+}
+
+/// The instruction with every input replaced.
+fn substitute<'a, W: Word>(
+    instruction: Instruction<'a, W>,
+    replace: impl Fn(Operand<'a, W>) -> Operand<'a, W>,
+) -> Instruction<'a, W> {
+    match instruction {
+        Instruction::Literal(..) => instruction,
+        Instruction::And(in1, in2, out) => Instruction::And(replace(in1), replace(in2), out),
+        Instruction::Or(in1, in2, out) => Instruction::Or(replace(in1), replace(in2), out),
+        Instruction::Xor(in1, in2, out) => Instruction::Xor(replace(in1), replace(in2), out),
+        Instruction::Nand(in1, in2, out) => Instruction::Nand(replace(in1), replace(in2), out),
+        Instruction::Nor(in1, in2, out) => Instruction::Nor(replace(in1), replace(in2), out),
+        Instruction::Not(input, out) => Instruction::Not(replace(input), out),
+        Instruction::Rshift(input, amount, out) => Instruction::Rshift(replace(input), amount, out),
+        Instruction::Lshift(input, amount, out) => Instruction::Lshift(replace(input), amount, out),
+        Instruction::Rrotate(input, amount, out) => {
+            Instruction::Rrotate(replace(input), amount, out)
+        }
+        Instruction::Lrotate(input, amount, out) => {
+            Instruction::Lrotate(replace(input), amount, out)
+        }
+        Instruction::Mux(select, in1, in2, out) => {
+            Instruction::Mux(replace(select), replace(in1), replace(in2), out)
+        }
+        Instruction::Move(input, out) => copy(replace(Operand::Wire(input)), out),
+    }
+}
+
+fn copy<'a, W: Word>(operand: Operand<'a, W>, out: &'a str) -> Instruction<'a, W> {
+    match operand {
+        Operand::Wire(input) => Instruction::Move(input, out),
+        Operand::Literal(value) => Instruction::Literal(value, out),
+    }
+}
+
+/// Rewrites gates whose signal does not depend on every input, given the
+/// already rewritten sources of earlier wires.
+fn simplify<'a, W: Word>(
+    instruction: Instruction<'a, W>,
+    sources: &HashMap<&str, Instruction<'a, W>>,
+) -> Instruction<'a, W> {
+    let zero = Operand::Literal(W::default());
+    let ones = Operand::Literal(!W::default());
+    let not = |input, out| simplify(Instruction::Not(input, out), sources);
+
+    match instruction {
+        Instruction::And(in1, in2, out) if in1 == ones || in1 == in2 => copy(in2, out),
+        Instruction::And(in1, in2, out) if in2 == ones => copy(in1, out),
+        Instruction::And(in1, in2, out) if in1 == zero || in2 == zero => copy(zero, out),
+        Instruction::Or(in1, in2, out) if in1 == zero || in1 == in2 => copy(in2, out),
+        Instruction::Or(in1, in2, out) if in2 == zero => copy(in1, out),
+        Instruction::Or(in1, in2, out) if in1 == ones || in2 == ones => copy(ones, out),
+        Instruction::Xor(in1, in2, out) if in1 == in2 => copy(zero, out),
+        Instruction::Xor(in1, in2, out) if in1 == zero => copy(in2, out),
+        Instruction::Xor(in1, in2, out) if in2 == zero => copy(in1, out),
+        Instruction::Xor(in1, in2, out) if in1 == ones => not(in2, out),
+        Instruction::Xor(in1, in2, out) if in2 == ones => not(in1, out),
+        Instruction::Nand(in1, in2, out) if in1 == ones || in1 == in2 => not(in2, out),
+        Instruction::Nand(in1, in2, out) if in2 == ones => not(in1, out),
+        Instruction::Nand(in1, in2, out) if in1 == zero || in2 == zero => copy(ones, out),
+        Instruction::Nor(in1, in2, out) if in1 == zero || in1 == in2 => not(in2, out),
+        Instruction::Nor(in1, in2, out) if in2 == zero => not(in1, out),
+        Instruction::Nor(in1, in2, out) if in1 == ones || in2 == ones => copy(zero, out),
+        Instruction::Not(Operand::Wire(input), out) => match sources.get(input) {
+            Some(Instruction::Not(inner, _)) => copy(*inner, out),
+            _ => instruction,
+        },
+        Instruction::Rshift(input, 0, out) | Instruction::Lshift(input, 0, out) => copy(input, out),
+        Instruction::Rshift(_, amount, out) | Instruction::Lshift(_, amount, out)
+            if u32::from(amount) >= W::BITS =>
+        {
+            copy(zero, out)
+        }
+        Instruction::Rrotate(input, amount, out) | Instruction::Lrotate(input, amount, out)
+            if u32::from(amount) % W::BITS == 0 =>
+        {
+            copy(input, out)
+        }
+        Instruction::Mux(select, in1, in2, out) if select == zero || in1 == in2 => copy(in1, out),
+        Instruction::Mux(select, _, in2, out) if select == ones => copy(in2, out),
+        Instruction::Mux(select, in1, in2, out) if in1 == zero && in2 == ones => copy(select, out),
+        _ => instruction,
+    }
+}
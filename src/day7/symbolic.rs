@@ -0,0 +1,244 @@
+/*!
+Wires as expressions over the inputs left unknown.
+
+The booklet is first rewritten by the optimiser with some wires as free
+inputs, so every wire that does not read one folds to a literal and the rest
+are already simplified. Each wire's expression then follows its gate back
+through earlier wires, sharing the expressions of wires read more than once,
+until it reaches literals and inputs. Written out in full, an expression can
+repeat those shared parts more times than fit in memory, so they are printed
+once each as a definition named after their wire.
+
+Separately, every wire records the source wires it is built from, whether or
+not their signals end up mattering: the wires given a literal, and the inputs.
+*/
+
+use super::optimise::rewrite;
+use super::{dependency_order, CircuitError, Instruction, Operand, Word};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Expr<'a, W: Word> {
+    Literal(W),
+    /// A wire whose signal is not known in advance.
+    Input(&'a str),
+    Not(Rc<Expr<'a, W>>),
+    /// A gate reading two signals, named as in the booklet.
+    Binary(&'static str, Rc<Expr<'a, W>>, Rc<Expr<'a, W>>),
+    /// A shift or rotate, named as in the booklet.
+    Shift(&'static str, Rc<Expr<'a, W>>, u8),
+    Mux(Rc<Expr<'a, W>>, Rc<Expr<'a, W>>, Rc<Expr<'a, W>>),
+}
+
+impl<'a, W: Word> Expr<'a, W> {
+    fn children(&self) -> Vec<&Rc<Self>> {
+        match self {
+            Expr::Literal(_) | Expr::Input(_) => vec![],
+            Expr::Not(input) | Expr::Shift(_, input, _) => vec![input],
+            Expr::Binary(_, in1, in2) => vec![in1, in2],
+            Expr::Mux(select, in1, in2) => vec![select, in1, in2],
+        }
+    }
+
+    /// The inputs the expression still reads.
+    pub fn inputs(&self) -> BTreeSet<&'a str> {
+        let mut inputs = BTreeSet::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            if !seen.insert(expr as *const Self) {
+                continue;
+            }
+            match expr {
+                Expr::Input(wire) => {
+                    inputs.insert(*wire);
+                }
+                expr => stack.extend(expr.children().into_iter().map(|child| &**child)),
+            }
+        }
+        inputs
+    }
+
+    fn is_leaf(&self) -> bool {
+        matches!(self, Expr::Literal(_) | Expr::Input(_))
+    }
+
+    /// The expression like the booklet, with each gate input written by
+    /// `nested`.
+    fn format_with(&self, nested: &dyn Fn(&Rc<Self>) -> String) -> String {
+        match self {
+            Expr::Literal(value) => value.to_string(),
+            Expr::Input(wire) => wire.to_string(),
+            Expr::Not(input) => format!("NOT {}", nested(input)),
+            Expr::Binary(gate, in1, in2) => format!("{} {} {}", nested(in1), gate, nested(in2)),
+            Expr::Shift(gate, input, amount) => format!("{} {} {}", nested(input), gate, amount),
+            Expr::Mux(select, in1, in2) => {
+                format!("MUX {} {} {}", nested(select), nested(in1), nested(in2))
+            }
+        }
+    }
+}
+
+/// Written like the booklet, with every gate but the outermost in brackets.
+impl<W: Word> fmt::Display for Expr<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nested = |expr: &Rc<Self>| match expr.is_leaf() {
+            true => expr.to_string(),
+            false => format!("({})", expr),
+        };
+        write!(f, "{}", self.format_with(&nested))
+    }
+}
+
+pub struct Symbolic<'a, W: Word> {
+    /// Every wire, in dependency order.
+    order: Vec<&'a str>,
+    expressions: HashMap<&'a str, Rc<Expr<'a, W>>>,
+    sources: HashMap<&'a str, BTreeSet<&'a str>>,
+}
+
+impl<'a, W: Word> Symbolic<'a, W> {
+    pub fn new(
+        booklet: &'a [Instruction<'a, W>],
+        inputs: &[&str],
+    ) -> Result<Self, CircuitError<'a>> {
+        let mut sources: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+        let mut order = Vec::with_capacity(booklet.len());
+        for instruction in dependency_order(booklet)? {
+            let wire = instruction.output();
+            let from = match instruction {
+                Instruction::Literal(..) => vec![wire].into_iter().collect(),
+                _ if inputs.contains(&wire) => vec![wire].into_iter().collect(),
+                _ => instruction
+                    .inputs()
+                    .iter()
+                    .flat_map(|input| sources[input].iter().copied())
+                    .collect(),
+            };
+            sources.insert(wire, from);
+            order.push(wire);
+        }
+
+        let (rewritten, _) = rewrite(booklet, inputs)?;
+        let mut expressions: HashMap<&str, Rc<Expr<W>>> = HashMap::new();
+        for instruction in rewritten {
+            let wire = instruction.output();
+            let operand = |operand| match operand {
+                Operand::Wire(input) => Rc::clone(&expressions[input]),
+                Operand::Literal(value) => Rc::new(Expr::Literal(value)),
+            };
+            let binary = |gate, in1, in2| Rc::new(Expr::Binary(gate, operand(in1), operand(in2)));
+            let shift = |gate, input, amount| Rc::new(Expr::Shift(gate, operand(input), amount));
+            let expr = match instruction {
+                _ if inputs.contains(&wire) => Rc::new(Expr::Input(wire)),
+                Instruction::Literal(value, _) => Rc::new(Expr::Literal(value)),
+                Instruction::Move(input, _) => operand(Operand::Wire(input)),
+                Instruction::And(in1, in2, _) => binary("AND", in1, in2),
+                Instruction::Or(in1, in2, _) => binary("OR", in1, in2),
+                Instruction::Xor(in1, in2, _) => binary("XOR", in1, in2),
+                Instruction::Nand(in1, in2, _) => binary("NAND", in1, in2),
+                Instruction::Nor(in1, in2, _) => binary("NOR", in1, in2),
+                Instruction::Not(input, _) => Rc::new(Expr::Not(operand(input))),
+                Instruction::Rshift(input, amount, _) => shift("RSHIFT", input, amount),
+                Instruction::Lshift(input, amount, _) => shift("LSHIFT", input, amount),
+                Instruction::Rrotate(input, amount, _) => shift("RROTATE", input, amount),
+                Instruction::Lrotate(input, amount, _) => shift("LROTATE", input, amount),
+                Instruction::Mux(select, in1, in2, _) => {
+                    Rc::new(Expr::Mux(operand(select), operand(in1), operand(in2)))
+                }
+            };
+            expressions.insert(wire, expr);
+        }
+
+        Ok(Self {
+            order,
+            expressions,
+            sources,
+        })
+    }
+
+    /// The wire's signal in terms of the inputs.
+    pub fn expression(&self, wire: &str) -> Option<&Expr<'a, W>> {
+        self.expressions.get(wire).map(|expr| &**expr)
+    }
+
+    /// The wires given a literal, and the inputs, that the wire is built from.
+    pub fn sources(&self, wire: &str) -> Option<&BTreeSet<&'a str>> {
+        self.sources.get(wire)
+    }
+
+    /// The wire's expression as definitions to read in order, ending with
+    /// the wire's own. Parts of it read more than once are defined by
+    /// themselves, named after the first wire carrying them.
+    pub fn definitions(&self, wire: &str) -> Option<Vec<(&'a str, String)>> {
+        let (wire, root) = self.expressions.get_key_value(wire)?;
+
+        let mut names = HashMap::new();
+        for name in &self.order {
+            names
+                .entry(Rc::as_ptr(&self.expressions[name]))
+                .or_insert(*name);
+        }
+        let mut uses: HashMap<*const Expr<W>, usize> = HashMap::new();
+        let mut stack = vec![root];
+        while let Some(expr) = stack.pop() {
+            for child in expr.children() {
+                let count = uses.entry(Rc::as_ptr(child)).or_insert(0);
+                *count += 1;
+                if *count == 1 {
+                    stack.push(child);
+                }
+            }
+        }
+        let shared = |expr: &Rc<Expr<W>>| {
+            let used = uses.get(&Rc::as_ptr(expr)).copied().unwrap_or(0);
+            !expr.is_leaf() && (Rc::ptr_eq(expr, root) || used > 1)
+        };
+
+        fn render<'a, W: Word>(
+            expr: &Expr<'a, W>,
+            shared: &dyn Fn(&Rc<Expr<'a, W>>) -> bool,
+            names: &HashMap<*const Expr<'a, W>, &'a str>,
+        ) -> String {
+            expr.format_with(&|child| {
+                if child.is_leaf() {
+                    child.to_string()
+                } else if shared(child) {
+                    names[&Rc::as_ptr(child)].to_string()
+                } else {
+                    format!("({})", render(child, shared, names))
+                }
+            })
+        }
+
+        let mut definitions = vec![];
+        for name in &self.order {
+            let expr = &self.expressions[name];
+            if shared(expr) && names[&Rc::as_ptr(expr)] == *name {
+                definitions.push((*name, render(expr, &shared, &names)));
+            }
+        }
+        // a literal, an input, or a copy of an earlier wire
+        if definitions.last().map(|(name, _)| name) != Some(wire) {
+            let expr = match shared(root) {
+                true => names[&Rc::as_ptr(root)].to_string(),
+                false => root.to_string(),
+            };
+            definitions.push((*wire, expr));
+        }
+        Some(definitions)
+    }
+
+    /// The wires whose signal can change with the input's, in dependency
+    /// order.
+    pub fn affected_by(&self, input: &str) -> Vec<&'a str> {
+        self.order
+            .iter()
+            .copied()
+            .filter(|wire| *wire != input && self.expressions[wire].inputs().contains(input))
+            .collect()
+    }
+}
@@ -0,0 +1,71 @@
+/*!
+The signal a wire carries.
+
+Bobby's kit uses 16-bit wires, but nothing else about the circuit depends on
+that, so any unsigned integer from `u8` to `u64` can stand in. Shifting by the
+width or more clears every bit, rather than overflowing as Rust's `<<` does.
+*/
+
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+use std::str::FromStr;
+
+pub trait Word:
+    Copy
+    + Default
+    + Eq
+    + Hash
+    + Debug
+    + Display
+    + FromStr
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Not<Output = Self>
+{
+    const BITS: u32;
+    /// The Rust type, for generated source.
+    const NAME: &'static str;
+
+    fn shl(self, amount: u32) -> Self;
+
+    fn shr(self, amount: u32) -> Self;
+
+    fn rotate_left(self, amount: u32) -> Self;
+
+    fn rotate_right(self, amount: u32) -> Self;
+
+    /// Each bit from `when_set` where `self` has a 1, and from `when_clear`
+    /// where it has a 0.
+    fn select(self, when_clear: Self, when_set: Self) -> Self {
+        (self & when_set) | (!self & when_clear)
+    }
+}
+
+macro_rules! word {
+    ($($t:ident),*) => {$(
+        impl Word for $t {
+            const BITS: u32 = $t::BITS;
+            const NAME: &'static str = stringify!($t);
+
+            fn shl(self, amount: u32) -> Self {
+                self.checked_shl(amount).unwrap_or(0)
+            }
+
+            fn shr(self, amount: u32) -> Self {
+                self.checked_shr(amount).unwrap_or(0)
+            }
+
+            fn rotate_left(self, amount: u32) -> Self {
+                $t::rotate_left(self, amount)
+            }
+
+            fn rotate_right(self, amount: u32) -> Self {
+                $t::rotate_right(self, amount)
+            }
+        }
+    )*};
+}
+
+word!(u8, u16, u32, u64);
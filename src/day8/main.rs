@@ -34,8 +34,9 @@ For example:
 Your task is to find the total number of characters to represent the newly encoded strings minus the number of characters of code in each original string literal. For example, for the strings above, the total encoded length (6 + 9 + 16 + 11 = 42) minus the characters in the original code representation (23, just like in the first part of this puzzle) is 42 - 23 = 19.
 */
 
-use util::load;
+use util::{invalid_data, load};
 
+use std::fmt;
 use std::io;
 
 fn main() -> io::Result<()> {
@@ -43,7 +44,7 @@ fn main() -> io::Result<()> {
 
     let list = SantasList::from(&contents);
 
-    println!("part1: {}", list.code_minus_memory());
+    println!("part1: {}", list.code_minus_memory().map_err(invalid_data)?);
     println!("part2: {}", list.encoded_minus_code());
 
     Ok(())
@@ -53,13 +54,13 @@ struct SantasList<'a>(Vec<MyString<'a>>);
 
 impl<'a> SantasList<'a> {
     fn from(s: &'a str) -> Self {
-        Self(s.lines().map(|line| MyString::from(line)).collect())
+        Self(s.lines().map(MyString::from).collect())
     }
 
-    fn code_minus_memory(&self) -> usize {
+    fn code_minus_memory(&self) -> Result<usize, DecodeError<'a>> {
         self.0
             .iter()
-            .map(|s| s.code_length() - s.memory_length())
+            .map(|s| Ok(s.code_length() - s.memory_length()?))
             .sum()
     }
 
@@ -86,50 +87,103 @@ impl<'a> MyString<'a> {
     }
 
     fn encoded_length(&self) -> usize {
-        // code_length + 2 + (number of \ and ")
-        let num_slash = self.inner.chars().filter(|c| *c == '\\').count();
-        let num_paren = self.inner.chars().filter(|c| *c == '"').count();
-        self.code_length() + 2 + num_slash + num_paren
-    }
-
-    fn memory_length(&self) -> usize {
-        let mut count = 0;
-        let mut escape = false;
-        let mut hex = 0;
-
-        for c in self.inner[1..self.inner.len() - 1].chars() {
-            if c == '\\' {
-                if escape {
-                    // for double \\
-                    count += 1;
-                    escape = false;
-                } else {
-                    escape = true;
-                }
-            } else if escape && c == 'x' {
-                hex = 2;
-            } else {
-                if hex == 2 {
-                    hex -= 1;
-                    continue;
-                } else if hex == 1 {
-                    hex = 0;
-                    escape = false;
-                } else if escape {
-                    escape = false;
-                }
-                count += 1;
+        self.encode().len()
+    }
+
+    fn memory_length(&self) -> Result<usize, DecodeError<'a>> {
+        Ok(self.decode()?.len())
+    }
+
+    /// The bytes the literal stands for, without its quotes.
+    fn decode(&self) -> Result<Vec<u8>, DecodeError<'a>> {
+        let literal = self.inner;
+        let inner = literal
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or(DecodeError::Unquoted(literal))?;
+        // offsets count the opening quote, to point into the literal
+        let mut bytes = inner.bytes().enumerate().map(|(i, byte)| (i + 1, byte));
+        let mut decoded = Vec::with_capacity(inner.len());
+        while let Some((at, byte)) = bytes.next() {
+            match byte {
+                b'"' => return Err(DecodeError::StrayQuote { literal, at }),
+                b'\\' => match bytes.next() {
+                    Some((_, escaped @ (b'\\' | b'"'))) => decoded.push(escaped),
+                    Some((_, b'x')) => {
+                        let mut hex = || {
+                            bytes
+                                .next()
+                                .and_then(|(_, digit)| (digit as char).to_digit(16))
+                        };
+                        match (hex(), hex()) {
+                            (Some(high), Some(low)) => decoded.push((high << 4 | low) as u8),
+                            _ => return Err(DecodeError::BadEscape { literal, at }),
+                        }
+                    }
+                    _ => return Err(DecodeError::BadEscape { literal, at }),
+                },
+                byte => decoded.push(byte),
+            }
+        }
+        Ok(decoded)
+    }
+
+    /// The literal itself written as a new literal.
+    fn encode(&self) -> String {
+        encode(self.inner.as_bytes())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DecodeError<'a> {
+    /// A literal that does not start and end with `"`.
+    Unquoted(&'a str),
+    /// A `\` not followed by `\`, `"`, or `x` and two hex digits, at this
+    /// byte of the literal.
+    BadEscape { literal: &'a str, at: usize },
+    /// A `"` inside the literal without a `\` before it.
+    StrayQuote { literal: &'a str, at: usize },
+}
+
+impl fmt::Display for DecodeError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Unquoted(literal) => write!(f, "{} is not in quotes", literal),
+            DecodeError::BadEscape { literal, at } => {
+                write!(f, "bad escape at byte {} of {}", at, literal)
+            }
+            DecodeError::StrayQuote { literal, at } => {
+                write!(f, "unescaped quote at byte {} of {}", at, literal)
             }
         }
+    }
+}
 
-        count
+/// A literal that decodes to `bytes`. Only `\\` and `"` are escaped among
+/// printable ASCII, and every other byte is written as `\xHH`.
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() + 2);
+    encoded.push('"');
+    for &byte in bytes {
+        match byte {
+            b'\\' | b'"' => {
+                encoded.push('\\');
+                encoded.push(byte as char);
+            }
+            b' '..=b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("\\x{:02x}", byte)),
+        }
     }
+    encoded.push('"');
+    encoded
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use util::Lcg;
+
     #[test]
     fn test_mystring_encoded_length() {
         assert_eq!(MyString::from(r#""""#).encoded_length(), 6);
@@ -145,8 +199,8 @@ mod tests {
 "aaa\"aaa"
 "\x27"
 "#;
-        let list = SantasList::from(&s);
-        assert_eq!(list.code_minus_memory(), 12);
+        let list = SantasList::from(s);
+        assert_eq!(list.code_minus_memory(), Ok(12));
     }
 
     #[test]
@@ -159,11 +213,98 @@ mod tests {
 
     #[test]
     fn test_mystring_memory_length() {
-        assert_eq!(MyString::from(r#""""#).memory_length(), 0);
-        assert_eq!(MyString::from(r#""abc""#).memory_length(), 3);
-        assert_eq!(MyString::from(r#""aaa\"aaa""#).memory_length(), 7);
-        assert_eq!(MyString::from(r#""\x27""#).memory_length(), 1);
+        assert_eq!(MyString::from(r#""""#).memory_length(), Ok(0));
+        assert_eq!(MyString::from(r#""abc""#).memory_length(), Ok(3));
+        assert_eq!(MyString::from(r#""aaa\"aaa""#).memory_length(), Ok(7));
+        assert_eq!(MyString::from(r#""\x27""#).memory_length(), Ok(1));
+
+        assert_eq!(MyString::from(r#""aaa\\aaa""#).memory_length(), Ok(7));
+    }
+
+    #[test]
+    fn test_mystring_decode() {
+        assert_eq!(MyString::from(r#""""#).decode().unwrap(), b"");
+        assert_eq!(MyString::from(r#""abc""#).decode().unwrap(), b"abc");
+        assert_eq!(
+            MyString::from(r#""aaa\"aaa""#).decode().unwrap(),
+            b"aaa\"aaa"
+        );
+        assert_eq!(MyString::from(r#""\x27""#).decode().unwrap(), b"'");
+        assert_eq!(
+            MyString::from(r#""\\\xfF\\x""#).decode().unwrap(),
+            b"\\\xff\\x"
+        );
+    }
+
+    #[test]
+    fn test_mystring_encode() {
+        assert_eq!(MyString::from(r#""""#).encode(), r#""\"\"""#);
+        assert_eq!(MyString::from(r#""abc""#).encode(), r#""\"abc\"""#);
+        assert_eq!(
+            MyString::from(r#""aaa\"aaa""#).encode(),
+            r#""\"aaa\\\"aaa\"""#
+        );
+        assert_eq!(MyString::from(r#""\x27""#).encode(), r#""\"\\x27\"""#);
+        assert_eq!(encode(b"\x00\n\xff"), r#""\x00\x0a\xff""#);
+    }
+
+    #[test]
+    fn test_mystring_decode_errors() {
+        for literal in &["", "\"", "abc", "\"abc", "abc\""] {
+            assert_eq!(
+                MyString::from(literal).decode(),
+                Err(DecodeError::Unquoted(literal))
+            );
+        }
+        let literal = r#""a\q""#;
+        assert_eq!(
+            MyString::from(literal).decode(),
+            Err(DecodeError::BadEscape { literal, at: 2 })
+        );
+        for literal in &[r#""\x4""#, r#""\xg0""#, r#""ab\""#] {
+            assert!(matches!(
+                MyString::from(literal).decode(),
+                Err(DecodeError::BadEscape { .. })
+            ));
+        }
+        let literal = r#""a"b""#;
+        assert_eq!(
+            MyString::from(literal).decode().unwrap_err().to_string(),
+            r#"unescaped quote at byte 2 of "a"b""#
+        );
+    }
+
+    fn random_bytes(lcg: &mut Lcg) -> Vec<u8> {
+        let len = lcg.below(20);
+        (0..len).map(|_| lcg.below(256) as u8).collect()
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        let mut lcg = Lcg(8);
+        for _ in 0..1000 {
+            let bytes = random_bytes(&mut lcg);
+            let literal = encode(&bytes);
+            assert_eq!(MyString::from(&literal).decode(), Ok(bytes), "{}", literal);
+        }
+    }
 
-        assert_eq!(MyString::from(r#""aaa\\aaa""#).memory_length(), 7);
+    #[test]
+    fn test_decode_round_trips() {
+        let mut lcg = Lcg(88);
+        for _ in 0..1000 {
+            let literal = encode(&random_bytes(&mut lcg));
+            let string = MyString::from(&literal);
+            let encoded = string.encode();
+            // encoding the code gives a literal that decodes back to it
+            assert_eq!(
+                MyString::from(&encoded).decode().unwrap(),
+                literal.as_bytes()
+            );
+            assert_eq!(string.encoded_length(), encoded.len());
+            let decoded = string.decode().unwrap();
+            assert_eq!(encode(&decoded), literal);
+            assert_eq!(string.memory_length(), Ok(decoded.len()));
+        }
     }
 }
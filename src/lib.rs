@@ -1,5 +1,7 @@
+use std::fmt;
 use std::fs::File;
 use std::io::{self, Read};
+use std::str::FromStr;
 
 pub fn load(path: &str) -> io::Result<String> {
     let mut file = File::open(path)?;
@@ -7,3 +9,95 @@ pub fn load(path: &str) -> io::Result<String> {
     file.read_to_string(&mut contents)?;
     Ok(contents)
 }
+
+/// The arguments that are not flags, skipping the value after any of
+/// `value_flags` so it is not mistaken for one. A lone `-` is positional.
+pub fn positional<'a>(args: &'a [String], value_flags: &[&str]) -> Vec<&'a str> {
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if value_flags.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with("--") {
+            positional.push(arg.as_str());
+        }
+    }
+    positional
+}
+
+/// The value given to a flag, the first time it appears.
+pub fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.windows(2)
+        .find(|pair| pair[0] == flag)
+        .map(|pair| pair[1].as_str())
+}
+
+/// Every value given to a flag that may be repeated.
+pub fn flag_values<'a>(args: &'a [String], flag: &'a str) -> impl Iterator<Item = &'a str> {
+    args.windows(2)
+        .filter(move |pair| pair[0] == flag)
+        .map(|pair| pair[1].as_str())
+}
+
+pub fn parse_flag<T: FromStr>(flag: &str, value: &str) -> io::Result<T> {
+    value.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("bad {} {:?}", flag, value),
+        )
+    })
+}
+
+pub fn invalid_data<E: fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// A seeded linear congruential generator, for tests that want varied but
+/// repeatable cases.
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    /// A number below `n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_positional() {
+        let args = args(&["abcdef", "--hash", "md5", "--bench", "pqrstuv", "-"]);
+        assert_eq!(
+            positional(&args, &["--hash"]),
+            vec!["abcdef", "pqrstuv", "-"]
+        );
+        assert_eq!(
+            positional(&args, &[]),
+            vec!["abcdef", "md5", "pqrstuv", "-"]
+        );
+    }
+
+    #[test]
+    fn test_flag_values() {
+        let args = args(&["--set", "a=1", "x", "--set", "b=2", "--bits"]);
+        assert_eq!(flag_value(&args, "--set"), Some("a=1"));
+        assert_eq!(
+            flag_values(&args, "--set").collect::<Vec<_>>(),
+            vec!["a=1", "b=2"]
+        );
+        assert_eq!(flag_value(&args, "--bits"), None);
+        assert_eq!(parse_flag::<u8>("--bits", "8").unwrap(), 8);
+        assert!(parse_flag::<u8>("--bits", "300").is_err());
+    }
+}